@@ -0,0 +1,104 @@
+//! CBZ archive builder.
+
+use crate::{format::Metadata, xml::escape, Direction, EncodedPage, MediaType};
+use eyre::{Result, WrapErr};
+use std::{
+    fmt::Write as _,
+    io::{Seek, Write},
+};
+use zip::{write::FileOptions, ZipWriter};
+
+/// Writes a CBZ archive out of the media pages into `writer`, page by page.
+///
+/// Pages are stored, in order, under a directory named after the media title.
+/// The metadata are stored in a `ComicInfo.xml` file, at the root of the
+/// archive.
+pub fn write<W: Write + Seek>(
+    writer: W,
+    metadata: &Metadata<'_>,
+    pages: impl Iterator<Item = Result<EncodedPage>>,
+) -> Result<W> {
+    let mut cbz = ZipWriter::new(writer);
+    let options = FileOptions::default();
+    let title = metadata.title;
+
+    // Add the media directory in the archive.
+    cbz.add_directory(title, options)
+        .context("create media directory")?;
+
+    // XXX: we can use enumerate because the pages are sorted.
+    let mut page_count = 0;
+    for (i, page) in pages.enumerate() {
+        let page = page.with_context(|| format!("process page {i:03}"))?;
+        let filename = format!("{i:03}.{}", page.extension());
+
+        // Add the page in the archive.
+        cbz.start_file(format!("{title}/{filename}"), options)
+            .with_context(|| format!("add image {filename}"))?;
+        cbz.write_all(&page.bytes)
+            .with_context(|| format!("write image {filename}"))?;
+        page_count += 1;
+    }
+
+    cbz.start_file("ComicInfo.xml", options)
+        .context("add ComicInfo.xml")?;
+    cbz.write_all(comic_info(metadata, page_count).as_bytes())
+        .context("write ComicInfo.xml")?;
+
+    cbz.finish().context("finalize archive")
+}
+
+/// Returns the content of `ComicInfo.xml`, as understood by Komga or Kavita.
+///
+/// See <https://anansi-project.github.io/docs/comicinfo/schemas/v2.0>
+fn comic_info(metadata: &Metadata<'_>, page_count: usize) -> String {
+    let number = match metadata.media_type {
+        MediaType::Episode => format!("<Number>{}</Number>", metadata.number),
+        MediaType::Volume => format!("<Volume>{}</Volume>", metadata.number),
+    };
+    let manga = match metadata.direction {
+        Direction::Rtl => "YesAndRightToLeft",
+        Direction::Ltr => "No",
+    };
+    // Optional elements, only present when known.
+    //
+    // ComicInfo elements are a sequence: their order matters.
+    let mut summary = String::new();
+    if let Some(description) = metadata.description {
+        let _ =
+            writeln!(summary, "  <Summary>{}</Summary>", escape(description));
+    }
+    let mut credits = String::new();
+    if !metadata.authors.is_empty() {
+        let _ = writeln!(
+            credits,
+            "  <Writer>{}</Writer>",
+            escape(&metadata.authors.join(", "))
+        );
+    }
+    if !metadata.genres.is_empty() {
+        let _ = writeln!(
+            credits,
+            "  <Genre>{}</Genre>",
+            escape(&metadata.genres.join(", "))
+        );
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>{title}</Title>
+  <Series>{serie}</Series>
+  {number}
+{summary}{credits}  <Web>{url}</Web>
+  <PageCount>{page_count}</PageCount>
+  <LanguageISO>{language}</LanguageISO>
+  <Manga>{manga}</Manga>
+</ComicInfo>
+"#,
+        title = escape(metadata.title),
+        serie = escape(metadata.serie),
+        url = escape(metadata.url.as_str()),
+        language = metadata.language,
+    )
+}
@@ -1,60 +1,194 @@
 //! HTTP client to interact with Piccoma website.
 
-use eyre::{Result, WrapErr};
+use crate::{cookies, fs, Region};
+use cookie_store::CookieStore;
+use eyre::{eyre, Result, WrapErr};
 use kuchiki::traits::*;
 use serde::de::DeserializeOwned;
-use std::{io::Read, thread, time::Duration};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
 use url::Url;
 
-/// Use the website URL as referer.
-const REFERER: &str = "https://piccoma.com/fr";
 /// User agent to reduce our visibility (trying at least...)
 const USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:92.0) Gecko/20100101 Firefox/92.0";
 
 /// A simple HTTP client, handle retry and delay.
+///
+/// Clones share the same rate limit: requests are spaced out by the delay,
+/// even when made from several threads.
 #[derive(Clone)]
 pub struct Client {
     /// HTTP client.
     agent: ureq::Agent,
+    /// Regional website.
+    region: Region,
+    /// Website root, every URL is derived from it.
+    base_url: Url,
     /// Delay between each request.
     delay: Duration,
+    /// Earliest time at which the next request can be made.
+    next_request: Arc<Mutex<Instant>>,
     /// Max number of retry for each request.
     retry: u8,
 }
 
 impl Client {
-    /// Initialize a new client.
-    pub fn new(retry: u8) -> Self {
+    /// Initialize a new client for the `region` website rooted at `base_url`.
+    pub fn new(region: Region, mut base_url: Url, retry: u8) -> Self {
+        // Make sure relative URLs are resolved under the root, not beside it.
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+
         Self {
             agent: ureq::builder().user_agent(USER_AGENT).build(),
-            /// 1s ought to be enough to avoid detection...
+            region,
+            base_url,
+            // 1s ought to be enough to avoid detection...
             delay: Duration::from_secs(1),
+            next_request: Arc::new(Mutex::new(Instant::now())),
             retry,
         }
     }
 
+    /// Restores the session saved at `path`, if any.
+    ///
+    /// Expired cookies are discarded, thus an expired session is equivalent to
+    /// no session at all. A session revoked by the website is still restored
+    /// though, see [`Client::check_session`].
+    pub fn with_session(self, path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(self);
+        }
+
+        let file = File::open(path)
+            .with_context(|| format!("open {}", path.display()))?;
+        let cookies = CookieStore::load_json(BufReader::new(file))
+            .map_err(|err| eyre!("load cookies: {err}"))?;
+
+        Ok(self.with_cookie_store(cookies))
+    }
+
+    /// Imports the website cookies from a Netscape cookies.txt file.
+    pub fn with_cookies_file(self, path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("read {}", path.display()))?;
+        let domain = self.base_url.host_str().unwrap_or_default();
+        let mut cookies = self.cookie_store().context("copy cookies")?;
+
+        for cookie in cookies::parse(&content)
+            .context("parse cookies")?
+            .into_iter()
+            .filter(|cookie| cookie.matches(domain))
+        {
+            cookies.parse(&cookie.to_header(), &cookie.url()?).map_err(
+                |err| eyre!("import cookie {}: {err}", cookie.name()),
+            )?;
+        }
+
+        Ok(self.with_cookie_store(cookies))
+    }
+
+    /// Uses `token` as session token.
+    pub fn with_access_token(self, token: &str) -> Result<Self> {
+        let mut cookies = self.cookie_store().context("copy cookies")?;
+        let cookie =
+            format!("{}={token}; Path=/", self.region.session_cookie());
+
+        cookies
+            .parse(&cookie, &self.base_url)
+            .map_err(|err| eyre!("import access token: {err}"))?;
+
+        Ok(self.with_cookie_store(cookies))
+    }
+
+    /// Saves the current session at `path`.
+    pub fn save_session(&self, path: &Path) -> Result<()> {
+        let buf = self.export_cookies()?;
+
+        if let Some(directory) = path.parent() {
+            fs::mkdir_p(directory).context("create session directory")?;
+        }
+        fs::atomic_write_private(path, &buf).context("write session")
+    }
+
+    /// Sets the delay between each request.
+    #[must_use]
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Returns the regional website targeted by the client.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Returns the website root URL.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Returns the absolute URL of `path`, relative to the website root.
+    pub fn url(&self, path: &str) -> Url {
+        self.base_url.join(path).expect("valid website URL")
+    }
+
     /// Tests if the client is logged in as a user.
     pub fn is_logged_in(&self) -> bool {
-        self.agent
-            .cookie_store()
-            .contains("piccoma.com", "/", "access_token")
+        let domain = self.base_url.host_str().unwrap_or_default();
+
+        self.agent.cookie_store().contains(
+            domain,
+            "/",
+            self.region.session_cookie(),
+        )
     }
 
-    /// Logs into the website using the specified credential.
-    pub fn login(&self, email: &str, password: &str) -> Result<()> {
+    /// Tests if the website accepts the session of the client.
+    ///
+    /// Having a session cookie isn't enough: the session may have been
+    /// revoked, or the cookie may not be a session at all.
+    pub fn check_session(&self) -> Result<bool> {
+        if !self.is_logged_in() {
+            return Ok(false);
+        }
+
+        let url = self.url(self.region.account_path());
         let request = self
             .agent
-            .request("POST", "https://piccoma.com/fr/api/auth/signin")
+            .request_url("GET", &url)
             .set("accept", "text/html");
 
-        request
-            .send_json(ureq::json!({
-                "email": email,
-                "password": password,
-                "redirect": REFERER,
-            }))
-            .context("login")?;
+        match self.call(request) {
+            // Guests are redirected to the login form.
+            Ok(response) => Ok(!Url::parse(response.get_url())
+                .is_ok_and(|url| url.path().contains("signin"))),
+            Err(err) if matches!(error_status(&err), Some(401 | 403)) => {
+                Ok(false)
+            },
+            Err(err) => Err(err.wrap_err("get account page")),
+        }
+    }
+
+    /// Logs into the website using the specified credential.
+    ///
+    /// On failure, the error can be downcasted to a [`LoginError`].
+    pub fn login(&self, email: &str, password: &str) -> Result<()> {
+        self.region.login(self, email, password)?;
+
+        // Don't trust the website, make sure we really have a session.
+        if !self.is_logged_in() {
+            return Err(LoginError::NoSession.into());
+        }
 
         Ok(())
     }
@@ -100,13 +234,83 @@ impl Client {
         Ok(())
     }
 
+    /// Replaces the cookies of the client.
+    fn with_cookie_store(mut self, cookies: CookieStore) -> Self {
+        self.agent = ureq::builder()
+            .user_agent(USER_AGENT)
+            .cookie_store(cookies)
+            .build();
+        self
+    }
+
+    /// Returns a copy of the client's cookies.
+    fn cookie_store(&self) -> Result<CookieStore> {
+        let buf = self.export_cookies()?;
+
+        CookieStore::load_json(buf.as_slice())
+            .map_err(|err| eyre!("load cookies: {err}"))
+    }
+
+    /// Serializes the client's cookies, as JSON lines.
+    fn export_cookies(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+
+        // Unlike `CookieStore::save_json`, session cookies are kept too:
+        // they're what keep us logged in.
+        for cookie in self.agent.cookie_store().iter_unexpired() {
+            serde_json::to_writer(&mut buf, cookie)
+                .context("serialize cookie")?;
+            buf.push(b'\n');
+        }
+
+        Ok(buf)
+    }
+
+    /// Posts `data` as JSON to `url`.
+    ///
+    /// HTTP error statuses are not errors: the response is returned as is.
+    pub(crate) fn post_json(
+        &self,
+        url: &Url,
+        data: &serde_json::Value,
+    ) -> Result<ureq::Response> {
+        thread::sleep(self.reserve_slot(Duration::ZERO));
+        let res = self
+            .agent
+            .request_url("POST", url)
+            .set("accept", "text/html")
+            .set("Referer", self.base_url.as_str())
+            .send_json(data);
+
+        keep_error_response(res)
+    }
+
+    /// Posts `data` as an URL-encoded form to `url`.
+    ///
+    /// HTTP error statuses are not errors: the response is returned as is.
+    pub(crate) fn post_form(
+        &self,
+        url: &Url,
+        data: &[(&str, &str)],
+    ) -> Result<ureq::Response> {
+        thread::sleep(self.reserve_slot(Duration::ZERO));
+        let res = self
+            .agent
+            .request_url("POST", url)
+            .set("accept", "text/html")
+            .set("Referer", self.base_url.as_str())
+            .send_form(data);
+
+        keep_error_response(res)
+    }
+
     /// Executes a request and handle retries.
     fn call(&self, request: ureq::Request) -> Result<ureq::Response> {
-        // Wait a bit, don't overload the site.
-        thread::sleep(self.delay);
+        // Wait for our turn, don't overload the site.
+        thread::sleep(self.reserve_slot(Duration::ZERO));
 
         // Set referer to looks kinda legit.
-        let request = request.set("Referer", REFERER);
+        let request = request.set("Referer", self.base_url.as_str());
 
         let mut i = 0;
         loop {
@@ -117,9 +321,10 @@ impl Client {
             if let Err(ureq::Error::Status(code, ref response)) = res {
                 // If we got a retryable error, we try again!
                 if is_request_retryable(code) && i <= self.retry {
+                    // Retries are requests too, they wait for their turn.
                     let delay = self.retry_delay(response);
 
-                    thread::sleep(delay);
+                    thread::sleep(self.reserve_slot(delay));
                     continue;
                 }
             }
@@ -128,6 +333,20 @@ impl Client {
         }
     }
 
+    /// Reserves the next request slot, at least `wait` from now, and returns
+    /// how long to wait for it.
+    fn reserve_slot(&self, wait: Duration) -> Duration {
+        let mut next_request = self
+            .next_request
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let slot = (*next_request).max(now + wait);
+        *next_request = slot + self.delay;
+
+        slot - now
+    }
+
     /// Computes the delay to wait before retrying a failed request.
     fn retry_delay(&self, response: &ureq::Response) -> Duration {
         response
@@ -137,8 +356,100 @@ impl Client {
     }
 }
 
+/// Turns HTTP error statuses back into responses.
+fn keep_error_response(
+    res: Result<ureq::Response, ureq::Error>,
+) -> Result<ureq::Response> {
+    match res {
+        Err(ureq::Error::Status(_, response)) => Ok(response),
+        res => res.context("HTTP request failed"),
+    }
+}
+
+/// Tests if the request failed because the access was denied.
+///
+/// Signed URLs are denied once they have expired.
+pub(crate) fn is_forbidden(err: &eyre::Report) -> bool {
+    error_status(err) == Some(403)
+}
+
+/// Returns the HTTP status of the failed request, if any.
+fn error_status(err: &eyre::Report) -> Option<u16> {
+    err.chain()
+        .find_map(|cause| match cause.downcast_ref::<ureq::Error>() {
+            Some(ureq::Error::Status(status, _)) => Some(*status),
+            _ => None,
+        })
+}
+
 /// Tests if request failed with a retryable error.
 fn is_request_retryable(http_status: u16) -> bool {
     // 429 is Too Many Requests
     (500..=599).contains(&http_status) || http_status == 429
 }
+
+// -----------------------------------------------------------------------------
+
+/// Reason of a login failure.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LoginError {
+    /// Wrong email or password.
+    BadCredentials,
+    /// Account locked, usually after too many failed attempts.
+    AccountLocked,
+    /// The website wants a CAPTCHA to be solved, can't do it from here.
+    CaptchaRequired,
+    /// Login rejected for another reason, with the website explanation.
+    Rejected(String),
+    /// Login seemingly succeeded, but no session was created.
+    NoSession,
+}
+
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadCredentials => write!(f, "invalid email or password"),
+            Self::AccountLocked => write!(f, "account locked"),
+            Self::CaptchaRequired => {
+                write!(f, "CAPTCHA required, login from a browser then retry")
+            },
+            Self::Rejected(reason) => write!(f, "login rejected: {reason}"),
+            Self::NoSession => write!(f, "no session created"),
+        }
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_from_root() {
+        let base_url = Url::parse("http://localhost:8080/fr").expect("URL");
+        let client = Client::new(Region::Fr, base_url, 0);
+
+        assert_eq!(
+            client.url("viewer/42/1337").as_str(),
+            "http://localhost:8080/fr/viewer/42/1337"
+        );
+    }
+
+    #[test]
+    fn shared_rate_limit() {
+        let base_url = Url::parse("http://localhost:8080/fr").expect("URL");
+        let client = Client::new(Region::Fr, base_url, 0)
+            .with_delay(Duration::from_secs(10));
+        let clone = client.clone();
+
+        let first = client.reserve_slot(Duration::ZERO);
+        let second = clone.reserve_slot(Duration::ZERO);
+        // A retry delay doesn't allow to skip the queue.
+        let retry = client.reserve_slot(Duration::from_secs(1));
+
+        assert_eq!(first, Duration::ZERO);
+        assert!(second > Duration::from_secs(9), "waited {second:?}");
+        assert!(retry > Duration::from_secs(19), "waited {retry:?}");
+    }
+}
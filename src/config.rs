@@ -0,0 +1,462 @@
+//! User configuration, providing defaults for the command-line options.
+
+use crate::{
+    Direction, Encoding, Kind, MediaType, OutputFormat, PageEncoder, Region,
+    SerieID,
+};
+use clap::{ArgEnum, Args};
+use eyre::{Result, WrapErr};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use url::Url;
+
+/// Content of the configuration file.
+///
+/// Top-level options apply to every serie, while `[serie.<ID>]` tables
+/// override them for a specific serie.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Options for every serie.
+    defaults: Options,
+    /// Options overrides, by serie ID.
+    serie: HashMap<String, Options>,
+}
+
+impl<'de> Deserialize<'de> for Config {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Unknown keys are rejected, which `#[serde(flatten)]` doesn't allow:
+        // the serie tables are split from the top-level options by hand.
+        let mut table = toml::value::Table::deserialize(deserializer)?;
+        let serie = table
+            .remove("serie")
+            .map(toml::Value::try_into)
+            .transpose()
+            .map_err(|err| D::Error::custom(format!("serie: {err}")))?
+            .unwrap_or_default();
+        let defaults = toml::Value::Table(table)
+            .try_into()
+            .map_err(D::Error::custom)?;
+
+        Ok(Self { defaults, serie })
+    }
+}
+
+impl Config {
+    /// Loads the configuration file at `path`.
+    ///
+    /// When no path is given, the default configuration file is used if it
+    /// exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match default_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("read {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("parse {}", path.display()))
+    }
+
+    /// Returns the ID of the series having a dedicated table, in order.
+    pub fn series(&self) -> Result<Vec<SerieID>> {
+        let mut series = self
+            .serie
+            .keys()
+            .map(|id| id.parse())
+            .collect::<Result<Vec<SerieID>>>()?;
+        series.sort_unstable();
+
+        Ok(series)
+    }
+
+    /// Returns the options for the specified serie, if any.
+    pub fn options(&self, serie: Option<SerieID>) -> Options {
+        let overrides = serie
+            .and_then(|id| self.serie.get(&id.to_string()))
+            .cloned()
+            .unwrap_or_default();
+
+        overrides.or(self.defaults.clone())
+    }
+}
+
+/// Options that can be set from both the command-line and the configuration.
+#[derive(Debug, Default, Clone, Args, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    /// Path to the output directory [default: .]
+    #[clap(short, long, global = true)]
+    output: Option<PathBuf>,
+
+    /// Media type to download [default: episode]
+    #[clap(
+        short = 't',
+        long = "type",
+        value_name = "TYPE",
+        arg_enum,
+        value_parser,
+        global = true
+    )]
+    #[serde(rename = "type", deserialize_with = "arg_enum")]
+    media_type: Option<MediaType>,
+
+    /// File format of the downloaded media [default: cbz]
+    #[clap(long, arg_enum, value_parser, global = true)]
+    #[serde(deserialize_with = "arg_enum")]
+    output_format: Option<OutputFormat>,
+
+    /// Image format of the pages (except in PDF) [default: webp-lossless, or
+    /// png for EPUB]
+    #[clap(long, arg_enum, value_parser, global = true)]
+    #[serde(deserialize_with = "arg_enum")]
+    encoding: Option<Encoding>,
+
+    /// Quality of the lossy image formats, from 1 to 100 [default: 90]
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        global = true
+    )]
+    quality: Option<u8>,
+
+    /// Keep the original image files of the pages that aren't scrambled
+    /// (`--passthrough=false` to re-encode them anyway) [default: false]
+    #[clap(
+        long,
+        value_name = "BOOL",
+        value_parser,
+        min_values = 0,
+        multiple_values = false,
+        require_equals = true,
+        default_missing_value = "true",
+        global = true
+    )]
+    passthrough: Option<bool>,
+
+    /// Page progression of the media, when supported [default: rtl, or ltr
+    /// for smartoons]
+    #[clap(long, arg_enum, value_parser, global = true)]
+    #[serde(deserialize_with = "arg_enum")]
+    direction: Option<Direction>,
+
+    /// Email to login.
+    #[clap(short, long, global = true)]
+    user: Option<String>,
+
+    /// Path to a file containing the password (first line only).
+    #[clap(long, global = true)]
+    password_file: Option<PathBuf>,
+
+    /// Number of pages downloaded concurrently [default: 4]
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..),
+        global = true
+    )]
+    page_workers: Option<u8>,
+
+    /// Number of media downloaded concurrently [default: 1]
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..),
+        global = true
+    )]
+    media_workers: Option<u8>,
+
+    /// Max number of requests per second, across every worker [default: 1]
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..),
+        global = true
+    )]
+    rate_limit: Option<u8>,
+
+    /// Max number of retry for HTTP requests [default: 3]
+    #[clap(long, global = true)]
+    retry: Option<u8>,
+
+    /// Regional website to download from [default: fr]
+    #[clap(long, arg_enum, value_parser, global = true)]
+    #[serde(deserialize_with = "arg_enum")]
+    region: Option<Region>,
+
+    /// Root URL of the website [default: the region's one]
+    #[clap(long, global = true)]
+    base_url: Option<Url>,
+
+    /// Path to the file where the session is saved.
+    #[clap(long, global = true)]
+    session_file: Option<PathBuf>,
+}
+
+impl Options {
+    /// Returns a copy of `self` where unset options are taken from `fallback`.
+    #[must_use]
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            output: self.output.or(fallback.output),
+            media_type: self.media_type.or(fallback.media_type),
+            output_format: self.output_format.or(fallback.output_format),
+            encoding: self.encoding.or(fallback.encoding),
+            quality: self.quality.or(fallback.quality),
+            passthrough: self.passthrough.or(fallback.passthrough),
+            direction: self.direction.or(fallback.direction),
+            user: self.user.or(fallback.user),
+            password_file: self.password_file.or(fallback.password_file),
+            page_workers: self.page_workers.or(fallback.page_workers),
+            media_workers: self.media_workers.or(fallback.media_workers),
+            rate_limit: self.rate_limit.or(fallback.rate_limit),
+            retry: self.retry.or(fallback.retry),
+            region: self.region.or(fallback.region),
+            base_url: self.base_url.or(fallback.base_url),
+            session_file: self.session_file.or(fallback.session_file),
+        }
+    }
+
+    /// Returns the path to the output directory.
+    pub fn output(&self) -> PathBuf {
+        self.output.clone().unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Returns the media type to download.
+    pub fn media_type(&self) -> MediaType {
+        self.media_type.unwrap_or(MediaType::Episode)
+    }
+
+    /// Returns the file format of the downloaded media.
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format.unwrap_or(OutputFormat::Cbz)
+    }
+
+    /// Returns the encoder of the pages.
+    ///
+    /// EPUB readers aren't required to support WebP, PNG is used instead
+    /// unless explicitly asked otherwise.
+    pub fn page_encoder(&self) -> PageEncoder {
+        let encoding = self.encoding.unwrap_or(match self.output_format() {
+            OutputFormat::Epub => Encoding::Png,
+            _ => Encoding::WebpLossless,
+        });
+
+        PageEncoder::new(encoding, self.quality.unwrap_or(90))
+    }
+
+    /// Returns true if the original files of unscrambled pages are kept.
+    pub fn passthrough(&self) -> bool {
+        self.passthrough.unwrap_or(false)
+    }
+
+    /// Returns the page progression of the media, of the given `kind`.
+    ///
+    /// Only manga are read from right to left.
+    pub fn direction(&self, kind: Option<Kind>) -> Direction {
+        self.direction.unwrap_or(match kind {
+            Some(Kind::Smartoon) => Direction::Ltr,
+            Some(Kind::Manga) | None => Direction::Rtl,
+        })
+    }
+
+    /// Returns the number of pages downloaded concurrently.
+    pub fn page_workers(&self) -> usize {
+        self.page_workers.unwrap_or(4).into()
+    }
+
+    /// Returns the number of media downloaded concurrently.
+    pub fn media_workers(&self) -> usize {
+        self.media_workers.unwrap_or(1).into()
+    }
+
+    /// Returns the delay between each request.
+    pub fn request_delay(&self) -> Duration {
+        Duration::from_secs(1) / u32::from(self.rate_limit.unwrap_or(1))
+    }
+
+    /// Returns the email to login, if any.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Returns the path to the password file, if any.
+    pub fn password_file(&self) -> Option<&Path> {
+        self.password_file.as_deref()
+    }
+
+    /// Returns the max number of retry for HTTP requests.
+    pub fn retry(&self) -> u8 {
+        self.retry.unwrap_or(3)
+    }
+
+    /// Returns the regional website to download from.
+    pub fn region(&self) -> Region {
+        self.region.unwrap_or(Region::Fr)
+    }
+
+    /// Returns the root URL of the website.
+    pub fn base_url(&self) -> Url {
+        self.base_url
+            .clone()
+            .unwrap_or_else(|| self.region().base_url())
+    }
+
+    /// Returns the path to the session file, if it can be located.
+    pub fn session_file(&self) -> Option<PathBuf> {
+        self.session_file.clone().or_else(|| {
+            dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .map(|directory| {
+                    directory.join("piconbiere").join("session.json")
+                })
+        })
+    }
+}
+
+/// Returns the default location of the configuration file.
+fn default_path() -> Option<PathBuf> {
+    dirs::config_dir()
+        .map(|directory| directory.join("piconbiere").join("config.toml"))
+}
+
+/// Deserializes an option value the same way the command-line does.
+fn arg_enum<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ArgEnum,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| T::from_str(&value, true).map_err(D::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+output = "/srv/manga"
+type = "volume"
+output-format = "epub"
+passthrough = true
+page-workers = 8
+retry = 5
+
+[serie.208]
+output = "/srv/webtoon"
+type = "episode"
+passthrough = false
+
+[serie.42]
+region = "jp"
+"#;
+
+    #[test]
+    fn defaults() {
+        let config = toml::from_str::<Config>(CONFIG).expect("valid config");
+
+        let options = config.options(None);
+
+        assert_eq!(options.output(), PathBuf::from("/srv/manga"));
+        assert_eq!(options.media_type(), MediaType::Volume);
+        assert_eq!(options.output_format(), OutputFormat::Epub);
+        assert_eq!(options.direction(None), Direction::Rtl);
+        assert_eq!(options.direction(Some(Kind::Manga)), Direction::Rtl);
+        assert_eq!(options.direction(Some(Kind::Smartoon)), Direction::Ltr);
+        assert!(options.passthrough());
+        assert_eq!(options.page_workers(), 8);
+        assert_eq!(options.media_workers(), 1);
+        assert_eq!(options.request_delay(), Duration::from_secs(1));
+        assert_eq!(options.retry(), 5);
+        assert_eq!(options.region(), Region::Fr);
+        assert_eq!(options.base_url().as_str(), "https://piccoma.com/fr/");
+    }
+
+    #[test]
+    fn serie_overrides() {
+        let config = toml::from_str::<Config>(CONFIG).expect("valid config");
+
+        let options = config.options(Some(208.into()));
+
+        assert_eq!(options.output(), PathBuf::from("/srv/webtoon"));
+        assert_eq!(options.media_type(), MediaType::Episode);
+        assert!(!options.passthrough());
+        assert_eq!(options.retry(), 5);
+    }
+
+    #[test]
+    fn command_line_precedence() {
+        let config = toml::from_str::<Config>(CONFIG).expect("valid config");
+        let cli = Options {
+            retry: Some(1),
+            passthrough: Some(false),
+            ..Options::default()
+        };
+
+        let options = cli.or(config.options(Some(42.into())));
+
+        assert_eq!(options.retry(), 1);
+        assert!(!options.passthrough());
+        assert_eq!(options.region(), Region::Jp);
+        assert_eq!(options.base_url().as_str(), "https://piccoma.com/web/");
+    }
+
+    #[test]
+    fn passthrough_flag() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Cli {
+            #[clap(flatten)]
+            options: Options,
+        }
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from([&["piconbiere"], args].concat())
+                .expect("valid arguments")
+                .options
+                .passthrough
+        };
+
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--passthrough"]), Some(true));
+        assert_eq!(parse(&["--passthrough=false"]), Some(false));
+    }
+
+    #[test]
+    fn configured_series() {
+        let config = toml::from_str::<Config>(CONFIG).expect("valid config");
+
+        let series = config.series().expect("valid serie IDs");
+
+        assert_eq!(series, vec![42.into(), 208.into()]);
+    }
+
+    #[test]
+    fn unknown_key() {
+        for config in [
+            r#"output_format = "epub""#,
+            "[series.208]\noutput = \"/srv/webtoon\"",
+            "[serie.208]\noutput_dir = \"/srv/webtoon\"",
+        ] {
+            let res = toml::from_str::<Config>(config);
+
+            assert!(res.is_err(), "{config} accepted");
+        }
+    }
+
+    #[test]
+    fn invalid_value() {
+        let res = toml::from_str::<Config>(r#"type = "chapter""#);
+
+        assert!(res.is_err());
+    }
+}
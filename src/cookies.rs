@@ -0,0 +1,195 @@
+//! Netscape cookies.txt format, as exported by browser extensions.
+
+use eyre::{bail, eyre, Result, WrapErr};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Prefix of the HTTP-only cookies.
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// A cookie from a cookies.txt file.
+#[derive(Debug)]
+pub(crate) struct NetscapeCookie {
+    /// Domain, without leading dot.
+    domain: String,
+    /// Is the cookie sent to subdomains?
+    include_subdomains: bool,
+    /// Path.
+    path: String,
+    /// Is the cookie restricted to HTTPS?
+    secure: bool,
+    /// Is the cookie hidden from JavaScript?
+    http_only: bool,
+    /// Number of seconds before expiry, `None` for a session cookie.
+    max_age: Option<u64>,
+    /// Name.
+    name: String,
+    /// Value.
+    value: String,
+}
+
+impl NetscapeCookie {
+    /// Returns the cookie name.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Tests if the cookie is sent to `host`.
+    pub(crate) fn matches(&self, host: &str) -> bool {
+        host == self.domain
+            || (self.include_subdomains
+                && host
+                    .strip_suffix(&self.domain)
+                    .is_some_and(|prefix| prefix.ends_with('.')))
+    }
+
+    /// Returns the URL the cookie could have been received from.
+    pub(crate) fn url(&self) -> Result<Url> {
+        let scheme = if self.secure { "https" } else { "http" };
+
+        Url::parse(&format!("{scheme}://{}{}", self.domain, self.path))
+            .with_context(|| format!("invalid cookie domain {}", self.domain))
+    }
+
+    /// Returns the cookie as a `Set-Cookie` header value.
+    pub(crate) fn to_header(&self) -> String {
+        let mut header =
+            format!("{}={}; Path={}", self.name, self.value, self.path);
+
+        if self.include_subdomains {
+            header.push_str(&format!("; Domain={}", self.domain));
+        }
+        if let Some(max_age) = self.max_age {
+            header.push_str(&format!("; Max-Age={max_age}"));
+        }
+        if self.secure {
+            header.push_str("; Secure");
+        }
+        if self.http_only {
+            header.push_str("; HttpOnly");
+        }
+
+        header
+    }
+}
+
+/// Parses the content of a cookies.txt file.
+///
+/// Expired cookies are skipped.
+pub(crate) fn parse(content: &str) -> Result<Vec<NetscapeCookie>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backward")
+        .as_secs();
+
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            // HTTP-only cookies are prefixed, like a comment.
+            let (line, http_only) = line
+                .strip_prefix(HTTP_ONLY_PREFIX)
+                .map_or((line, false), |line| (line, true));
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            Some(
+                parse_line(line, http_only, now).with_context(|| {
+                    format!("invalid cookie at line {}", i + 1)
+                }),
+            )
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+/// Parses a cookie line, returns `None` if the cookie is expired.
+fn parse_line(
+    line: &str,
+    http_only: bool,
+    now: u64,
+) -> Result<Option<NetscapeCookie>> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    let &[domain, include_subdomains, path, secure, expires, name, value] =
+        fields.as_slice()
+    else {
+        bail!("expected 7 fields, got {}", fields.len());
+    };
+
+    let expires = expires
+        .parse::<u64>()
+        .map_err(|err| eyre!("invalid expiry {expires}: {err}"))?;
+    let max_age = match expires {
+        // Session cookie.
+        0 => None,
+        expires if expires <= now => return Ok(None),
+        expires => Some(expires - now),
+    };
+
+    Ok(Some(NetscapeCookie {
+        domain: domain.trim_start_matches('.').to_owned(),
+        include_subdomains: parse_bool(include_subdomains)?,
+        path: path.to_owned(),
+        secure: parse_bool(secure)?,
+        http_only,
+        max_age,
+        name: name.to_owned(),
+        value: value.to_owned(),
+    }))
+}
+
+/// Parses a cookies.txt boolean.
+fn parse_bool(value: &str) -> Result<bool> {
+    Ok(match value {
+        "TRUE" => true,
+        "FALSE" => false,
+        _ => bail!("invalid boolean {value}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIES: &str = "\
+# Netscape HTTP Cookie File
+# This is a generated file! Do not edit.
+
+.piccoma.com\tTRUE\t/\tTRUE\t4102444800\taccess_token\tfoo
+#HttpOnly_piccoma.com\tFALSE\t/fr\tFALSE\t0\tsession\tbar
+.piccoma.com\tTRUE\t/\tFALSE\t1000000000\texpired\tbaz
+";
+
+    #[test]
+    fn parse_cookies() {
+        let cookies = parse(COOKIES).expect("valid cookies");
+
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies[0].to_header().starts_with(
+            "access_token=foo; Path=/; Domain=piccoma.com; Max-Age="
+        ));
+        assert_eq!(cookies[1].to_header(), "session=bar; Path=/fr; HttpOnly");
+        assert_eq!(
+            cookies[1].url().expect("URL").as_str(),
+            "http://piccoma.com/fr"
+        );
+    }
+
+    #[test]
+    fn match_domain() {
+        let cookies = parse(COOKIES).expect("valid cookies");
+
+        assert!(cookies[0].matches("piccoma.com"));
+        assert!(cookies[0].matches("cdn.piccoma.com"));
+        assert!(!cookies[0].matches("notpiccoma.com"));
+        assert!(!cookies[1].matches("cdn.piccoma.com"));
+    }
+
+    #[test]
+    fn invalid_cookie() {
+        let res = parse("piccoma.com\tFALSE\t/\tFALSE\t0\taccess_token");
+
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Plain directory output, with one image file per page.
+
+use crate::EncodedPage;
+use eyre::{Result, WrapErr};
+use std::{fs as stdfs, path::Path};
+
+/// Writes the media pages in the directory `path`.
+///
+/// Pages are written one by one in a staging directory, renamed to `path`
+/// once every page is there: an existing media directory is always complete.
+/// The staging directory is removed on failure.
+pub fn write(
+    path: &Path,
+    pages: impl Iterator<Item = Result<EncodedPage>>,
+) -> Result<()> {
+    let staging = crate::fs::part_path(path);
+
+    // Leftovers of an interrupted run may have been encoded differently.
+    if staging.exists() {
+        stdfs::remove_dir_all(&staging)
+            .with_context(|| format!("remove {}", staging.display()))?;
+    }
+    crate::fs::mkdir_p(&staging).context("create staging directory")?;
+
+    let res = write_pages(&staging, pages).and_then(|()| {
+        stdfs::rename(&staging, path)
+            .with_context(|| format!("rename to {}", path.display()))
+    });
+    if res.is_err() {
+        // Best effort, the next run clears it anyway.
+        let _ = stdfs::remove_dir_all(&staging);
+    }
+
+    res
+}
+
+/// Writes the pages in the directory `path`.
+fn write_pages(
+    path: &Path,
+    pages: impl Iterator<Item = Result<EncodedPage>>,
+) -> Result<()> {
+    // XXX: we can use enumerate because the pages are sorted.
+    for (i, page) in pages.enumerate() {
+        let page = page.with_context(|| format!("process page {i:03}"))?;
+
+        let filename = format!("{i:03}.{}", page.extension());
+        crate::fs::atomic_write(&path.join(&filename), &page.bytes)
+            .with_context(|| format!("save {filename}"))?;
+    }
+
+    Ok(())
+}
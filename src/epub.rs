@@ -0,0 +1,270 @@
+//! Fixed-layout EPUB builder.
+
+use crate::{format::Metadata, xml::escape, EncodedPage};
+use eyre::{ensure, Result, WrapErr};
+use std::{
+    fmt::Write as _,
+    io::{Cursor, Seek, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// Points the reading systems to the package document.
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// Builds, in-memory, a fixed-layout EPUB 3 out of the media pages.
+///
+/// Each page is an image, displayed by its own XHTML document sized after the
+/// image. The first page is used as cover.
+pub fn build(
+    metadata: &Metadata<'_>,
+    pages: impl Iterator<Item = Result<EncodedPage>>,
+) -> Result<Vec<u8>> {
+    let mut epub = ZipWriter::new(Cursor::new(Vec::new()));
+
+    // The mimetype must be the first entry, uncompressed.
+    epub.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )
+    .context("add mimetype")?;
+    epub.write_all(b"application/epub+zip")
+        .context("write mimetype")?;
+    add_file(&mut epub, "META-INF/container.xml", CONTAINER.as_bytes())?;
+
+    // XXX: we can use enumerate because the pages are sorted.
+    // Original pages keep their format, so it may vary from page to page.
+    let mut images = Vec::new();
+    for (i, page) in pages.enumerate() {
+        let number = i + 1;
+        let page = page.with_context(|| format!("process page {number}"))?;
+
+        let extension = page.extension();
+        add_file(
+            &mut epub,
+            &format!("OEBPS/images/{number:03}.{extension}"),
+            &page.bytes,
+        )?;
+        add_file(
+            &mut epub,
+            &format!("OEBPS/pages/{number:03}.xhtml"),
+            page_document(metadata, number, extension, page.dimensions)
+                .as_bytes(),
+        )?;
+        images.push((extension, page.media_type()));
+    }
+    ensure!(!images.is_empty(), "no page");
+
+    add_file(
+        &mut epub,
+        "OEBPS/nav.xhtml",
+        nav_document(metadata, images.len()).as_bytes(),
+    )?;
+    add_file(
+        &mut epub,
+        "OEBPS/content.opf",
+        package_document(metadata, &images).as_bytes(),
+    )?;
+
+    Ok(epub.finish().expect("close in-memory zip").into_inner())
+}
+
+/// Adds a compressed file in the archive.
+fn add_file<W: Write + Seek>(
+    epub: &mut ZipWriter<W>,
+    name: &str,
+    content: &[u8],
+) -> Result<()> {
+    epub.start_file(name, FileOptions::default())
+        .with_context(|| format!("add {name}"))?;
+    epub.write_all(content)
+        .with_context(|| format!("write {name}"))
+}
+
+/// Returns the XHTML document displaying the page `number`.
+fn page_document(
+    metadata: &Metadata<'_>,
+    number: usize,
+    extension: &str,
+    (width, height): (u32, u32),
+) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="{language}">
+  <head>
+    <title>{title} - {number}</title>
+    <meta name="viewport" content="width={width}, height={height}"/>
+    <style>body {{ margin: 0; }} img {{ display: block; width: 100%; height: 100%; }}</style>
+  </head>
+  <body>
+    <img src="../images/{number:03}.{extension}" alt="{number}"/>
+  </body>
+</html>
+"#,
+        language = metadata.language,
+        title = escape(metadata.title),
+    )
+}
+
+/// Returns the navigation document: the table of contents and the page list.
+fn nav_document(metadata: &Metadata<'_>, page_count: usize) -> String {
+    let title = escape(metadata.title);
+    let pages = (1..=page_count).fold(String::new(), |mut acc, number| {
+        let _ = writeln!(
+            acc,
+            r#"        <li><a href="pages/{number:03}.xhtml">{number}</a></li>"#
+        );
+        acc
+    });
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}">
+  <head>
+    <title>{title}</title>
+  </head>
+  <body>
+    <nav epub:type="toc" id="toc">
+      <ol>
+        <li><a href="pages/001.xhtml">{title}</a></li>
+      </ol>
+    </nav>
+    <nav epub:type="page-list" hidden="">
+      <ol>
+{pages}      </ol>
+    </nav>
+  </body>
+</html>
+"#,
+        language = metadata.language,
+    )
+}
+
+/// Returns the package document: metadata, manifest and spine.
+///
+/// `images` holds the file extension and media type of each page image.
+fn package_document(
+    metadata: &Metadata<'_>,
+    images: &[(&str, &str)],
+) -> String {
+    let mut details = String::new();
+    for author in metadata.authors {
+        let _ = writeln!(
+            details,
+            "    <dc:creator>{}</dc:creator>",
+            escape(author)
+        );
+    }
+    for genre in metadata.genres {
+        let _ =
+            writeln!(details, "    <dc:subject>{}</dc:subject>", escape(genre));
+    }
+    if let Some(description) = metadata.description {
+        let _ = writeln!(
+            details,
+            "    <dc:description>{}</dc:description>",
+            escape(description)
+        );
+    }
+
+    let (mut manifest, mut spine) = (String::new(), String::new());
+    for (number, (extension, media_type)) in (1_usize..).zip(images) {
+        let properties = if number == 1 {
+            r#" properties="cover-image""#
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            manifest,
+            r#"    <item id="image-{number:03}" href="images/{number:03}.{extension}" media-type="{media_type}"{properties}/>
+    <item id="page-{number:03}" href="pages/{number:03}.xhtml" media-type="application/xhtml+xml"/>"#
+        );
+        let _ = writeln!(spine, r#"    <itemref idref="page-{number:03}"/>"#);
+    }
+
+    format!(
+        r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid" xml:lang="{language}">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+{details}    <meta property="dcterms:modified">{modified}</meta>
+    <meta property="belongs-to-collection" id="serie">{serie}</meta>
+    <meta refines="#serie" property="collection-type">series</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="rendition:orientation">auto</meta>
+    <meta property="rendition:spread">landscape</meta>
+    <meta name="cover" content="image-001"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine page-progression-direction="{direction}">
+{spine}  </spine>
+</package>
+"##,
+        language = metadata.language,
+        identifier = escape(&metadata.identifier),
+        title = escape(metadata.title),
+        serie = escape(metadata.serie),
+        modified = timestamp(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        ),
+        direction = metadata.direction.as_str(),
+    )
+}
+
+/// Formats a Unix timestamp as an UTC date-time (e.g. 2022-06-30T00:00:00Z).
+fn timestamp(secs: u64) -> String {
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from the number of days since the epoch.
+    // See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_timestamp() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_656_547_200), "2022-06-30T00:00:00Z");
+        assert_eq!(timestamp(1_672_531_199), "2022-12-31T23:59:59Z");
+    }
+}
@@ -0,0 +1,281 @@
+//! Output formats of the downloaded media.
+
+use crate::{Client, Media, MediaType, PageContent, Serie};
+use clap::ArgEnum;
+use eyre::{eyre, Result};
+use image::{
+    codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat, ImageOutputFormat,
+};
+use std::{borrow::Cow, fmt, io::Cursor};
+use url::Url;
+
+/// File format of a downloaded media.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
+pub enum OutputFormat {
+    /// Comic book archive.
+    Cbz,
+    /// Fixed-layout EPUB 3.
+    Epub,
+    /// PDF, of JPEG images.
+    Pdf,
+    /// Plain directory, of image files.
+    Directory,
+}
+
+impl OutputFormat {
+    /// Returns the file extension of the format, if it's a file.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::Cbz => Some("cbz"),
+            Self::Epub => Some("epub"),
+            Self::Pdf => Some("pdf"),
+            Self::Directory => None,
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Cbz => "CBZ",
+                Self::Epub => "EPUB",
+                Self::Pdf => "PDF",
+                Self::Directory => "directory",
+            }
+        )
+    }
+}
+
+/// Page progression of a media.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
+pub enum Direction {
+    /// Right-to-left, as in manga.
+    Rtl,
+    /// Left-to-right, as in western comics.
+    Ltr,
+}
+
+impl Direction {
+    /// Returns the direction, as used in EPUB.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Rtl => "rtl",
+            Self::Ltr => "ltr",
+        }
+    }
+}
+
+/// Metadata of a media, stored alongside its pages.
+#[derive(Debug)]
+pub struct Metadata<'a> {
+    /// Media title.
+    pub(crate) title: &'a str,
+    /// Media number in the serie.
+    pub(crate) number: u16,
+    /// Media type.
+    pub(crate) media_type: MediaType,
+    /// Serie title.
+    pub(crate) serie: &'a str,
+    /// Serie authors.
+    pub(crate) authors: &'a [String],
+    /// Serie genres.
+    pub(crate) genres: &'a [String],
+    /// Serie synopsis.
+    pub(crate) description: Option<&'a str>,
+    /// URL of the serie page.
+    pub(crate) url: Url,
+    /// Unique identifier of the media.
+    pub(crate) identifier: String,
+    /// Language of the media, as a BCP 47 tag.
+    pub(crate) language: &'static str,
+    /// Page progression.
+    pub(crate) direction: Direction,
+}
+
+impl<'a> Metadata<'a> {
+    /// Initializes the metadata of `media`, from the website of `client`.
+    pub fn new(
+        client: &Client,
+        serie: &'a Serie,
+        media: &'a Media,
+        direction: Direction,
+    ) -> Self {
+        let region = client.region();
+
+        Self {
+            title: media.title(),
+            number: media.number(),
+            media_type: media.media_type(),
+            serie: serie.title(),
+            authors: serie.authors(),
+            genres: serie.genres(),
+            description: serie.description(),
+            url: client
+                .url(&region.product_path(serie.id(), media.media_type())),
+            identifier: format!(
+                "urn:piccoma:{region}:{}:{}",
+                serie.id(),
+                media.id()
+            ),
+            language: region.language(),
+            direction,
+        }
+    }
+}
+
+/// Image format of the pages.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
+pub enum Encoding {
+    /// Lossless WebP: exact pixels, but large and slow to encode.
+    WebpLossless,
+    /// Lossy WebP.
+    Webp,
+    /// JPEG.
+    Jpeg,
+    /// PNG (lossless).
+    Png,
+}
+
+/// Encodes the pages, in a given format and quality.
+#[derive(Debug, Clone, Copy)]
+pub struct PageEncoder {
+    /// Image format.
+    encoding: Encoding,
+    /// Quality of the lossy formats, from 1 to 100.
+    quality: u8,
+}
+
+impl PageEncoder {
+    /// Initializes a new encoder.
+    ///
+    /// `quality` is clamped between 1 and 100, and only used by lossy formats.
+    pub fn new(encoding: Encoding, quality: u8) -> Self {
+        Self {
+            encoding,
+            quality: quality.clamp(1, 100),
+        }
+    }
+
+    /// Returns the file extension of the encoded pages.
+    pub fn extension(self) -> &'static str {
+        extension(self.format())
+    }
+
+    /// Returns the image format of the encoded pages.
+    fn format(self) -> ImageFormat {
+        match self.encoding {
+            Encoding::WebpLossless | Encoding::Webp => ImageFormat::WebP,
+            Encoding::Jpeg => ImageFormat::Jpeg,
+            Encoding::Png => ImageFormat::Png,
+        }
+    }
+
+    /// Encodes a page, unless it's an original file that can be kept as-is.
+    pub fn encode(self, page: PageContent) -> Result<EncodedPage> {
+        let dimensions = page.dimensions()?;
+
+        Ok(match page {
+            PageContent::Original { bytes, format } => EncodedPage {
+                bytes,
+                format,
+                dimensions,
+            },
+            PageContent::Image(ref image) => EncodedPage {
+                bytes: self.encode_image(image)?,
+                format: self.format(),
+                dimensions,
+            },
+        })
+    }
+
+    /// Encodes a decoded page.
+    fn encode_image(self, page: &DynamicImage) -> Result<Vec<u8>> {
+        match self.encoding {
+            Encoding::WebpLossless | Encoding::Webp => {
+                // WebP only supports RGB(A) images.
+                let page = match *page {
+                    DynamicImage::ImageRgb8(_)
+                    | DynamicImage::ImageRgba8(_) => Cow::Borrowed(page),
+                    _ => Cow::Owned(DynamicImage::ImageRgba8(page.to_rgba8())),
+                };
+                let encoder = webp::Encoder::from_image(&page)
+                    .map_err(|err| eyre!("{err}"))?;
+                let bytes = if self.encoding == Encoding::Webp {
+                    encoder.encode(f32::from(self.quality))
+                } else {
+                    encoder.encode_lossless()
+                };
+
+                Ok(bytes.to_vec())
+            },
+            Encoding::Jpeg => {
+                // JPEG doesn't support transparency.
+                let mut bytes = Vec::new();
+                JpegEncoder::new_with_quality(&mut bytes, self.quality)
+                    .encode_image(&DynamicImage::ImageRgb8(page.to_rgb8()))?;
+
+                Ok(bytes)
+            },
+            Encoding::Png => {
+                let mut bytes = Cursor::new(Vec::new());
+                page.write_to(&mut bytes, ImageOutputFormat::Png)?;
+
+                Ok(bytes.into_inner())
+            },
+        }
+    }
+}
+
+impl Default for PageEncoder {
+    fn default() -> Self {
+        Self::new(Encoding::WebpLossless, 90)
+    }
+}
+
+/// A page, ready to be stored.
+#[derive(Debug)]
+pub struct EncodedPage {
+    /// File content.
+    pub(crate) bytes: Vec<u8>,
+    /// Image format.
+    pub(crate) format: ImageFormat,
+    /// Width and height, in pixels.
+    pub(crate) dimensions: (u32, u32),
+}
+
+impl EncodedPage {
+    /// Returns the file extension of the page.
+    pub(crate) fn extension(&self) -> &'static str {
+        extension(self.format)
+    }
+
+    /// Returns the media type of the page.
+    pub(crate) fn media_type(&self) -> &'static str {
+        media_type(self.format).expect("storable image format")
+    }
+}
+
+/// Formats in which the original pages can be kept.
+///
+/// Only the ones that can be read back: `image` is built without WebP nor GIF
+/// support.
+pub(crate) const ORIGINAL_FORMATS: [ImageFormat; 2] =
+    [ImageFormat::Jpeg, ImageFormat::Png];
+
+/// Returns the preferred file extension of `format`.
+pub(crate) fn extension(format: ImageFormat) -> &'static str {
+    format.extensions_str().first().copied().unwrap_or("bin")
+}
+
+/// Returns the media type of `format`, if pages can be stored in it.
+pub(crate) fn media_type(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::WebP => Some("image/webp"),
+        _ => None,
+    }
+}
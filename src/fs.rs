@@ -4,7 +4,9 @@ use eyre::{Result, WrapErr};
 use once_cell::unsync::Lazy;
 use regex::Regex;
 use std::{
-    fs,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 
@@ -28,14 +30,13 @@ pub fn sanitize_name(name: &str) -> PathBuf {
 
 /// Recursively create a directory and all of its parent if necessary.
 pub fn mkdir_p(path: &Path) -> Result<()> {
-    fs::create_dir_all(&path)
+    fs::create_dir_all(path)
         .with_context(|| format!("mkdir_p {}", path.display()))
 }
 
 /// Write a file atomically (using a tempfile + atomic rename).
 pub fn atomic_write(path: &Path, data: &[u8]) -> Result<()> {
-    let mut tmp_path = path.to_path_buf();
-    tmp_path.set_extension("part");
+    let tmp_path = part_path(path);
 
     fs::write(&tmp_path, data)
         .with_context(|| format!("write {}", tmp_path.display()))?;
@@ -46,6 +47,116 @@ pub fn atomic_write(path: &Path, data: &[u8]) -> Result<()> {
     Ok(())
 }
 
+/// Write a file atomically, only readable by its owner (on Unix platforms).
+///
+/// The tempfile is restricted from the start: the content is never exposed.
+pub fn atomic_write_private(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp_path = part_path(path);
+
+    // Permissions only apply to new files, not to a leftover tempfile.
+    match fs::remove_file(&tmp_path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            return Err(err)
+                .with_context(|| format!("remove {}", tmp_path.display()));
+        },
+        _ => {},
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+    options
+        .open(&tmp_path)
+        .and_then(|mut file| file.write_all(data))
+        .with_context(|| format!("write {}", tmp_path.display()))?;
+
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("rename to {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Returns the path where `path` is written before being complete.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part_path = OsString::from(path.as_os_str());
+    part_path.push(".part");
+
+    part_path.into()
+}
+
+/// A file written atomically, for content too large to be kept in memory.
+///
+/// Content is written in a tempfile, renamed on commit. The tempfile is
+/// removed if the file is dropped without being committed.
+pub struct AtomicFile {
+    /// Buffered tempfile.
+    file: BufWriter<File>,
+    /// Path of the tempfile.
+    tmp_path: PathBuf,
+    /// Final path of the file.
+    path: PathBuf,
+    /// Has the file been renamed to its final path?
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates the tempfile of `path`.
+    pub fn create(path: &Path) -> Result<Self> {
+        let tmp_path = part_path(path);
+        let file = File::create(&tmp_path)
+            .with_context(|| format!("create {}", tmp_path.display()))?;
+
+        Ok(Self {
+            file: BufWriter::new(file),
+            tmp_path,
+            path: path.to_path_buf(),
+            committed: false,
+        })
+    }
+
+    /// Flushes the content, and moves the file to its final path.
+    pub fn commit(mut self) -> Result<()> {
+        self.file
+            .flush()
+            .with_context(|| format!("write {}", self.tmp_path.display()))?;
+        fs::rename(&self.tmp_path, &self.path)
+            .with_context(|| format!("rename to {}", self.path.display()))?;
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            // Best effort, a leftover tempfile is harmless.
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +185,46 @@ mod tests {
         assert_eq!(sanitize_name("foo\\bar"), expected);
         assert_eq!(sanitize_name("foo\"bar"), expected);
     }
+
+    #[test]
+    fn atomic_file() {
+        let path = std::env::temp_dir()
+            .join(format!("piconbiere-atomic-{}.cbz", std::process::id()));
+        let tmp_path = part_path(&path);
+
+        let mut file = AtomicFile::create(&path).expect("create file");
+        file.write_all(b"foo").expect("write");
+        assert!(tmp_path.is_file());
+        assert!(!path.exists());
+        file.commit().expect("commit");
+
+        assert!(!tmp_path.exists());
+        assert_eq!(fs::read(&path).expect("read"), b"foo");
+
+        let file = AtomicFile::create(&path).expect("create file");
+        drop(file);
+
+        assert!(!tmp_path.exists());
+        assert_eq!(fs::read(&path).expect("read"), b"foo");
+        fs::remove_file(&path).expect("cleanup");
+    }
+
+    #[test]
+    fn private_file() {
+        let path = std::env::temp_dir()
+            .join(format!("piconbiere-private-{}.json", std::process::id()));
+
+        atomic_write_private(&path, b"secret").expect("write");
+
+        assert_eq!(fs::read(&path).expect("read"), b"secret");
+        assert!(!part_path(&path).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let metadata = fs::metadata(&path).expect("metadata");
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        }
+        fs::remove_file(&path).expect("cleanup");
+    }
 }
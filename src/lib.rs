@@ -1,16 +1,31 @@
+pub mod cbz;
+pub mod config;
+pub mod directory;
+pub mod epub;
 pub mod fs;
+pub mod pdf;
+pub mod pipeline;
+pub mod sidecar;
 pub mod termio;
 
 mod client;
+mod cookies;
+mod format;
 mod media;
 mod models;
 mod page;
+mod region;
 mod selectors;
 mod serie;
+mod xml;
 
-pub use client::Client;
-pub use media::{Media, MediaType};
-pub use page::PageIterator;
-pub use serie::{Serie, SerieID};
+pub use client::{Client, LoginError};
+pub use format::{
+    Direction, EncodedPage, Encoding, Metadata, OutputFormat, PageEncoder,
+};
+pub use media::{AccessType, Media, MediaType};
+pub use page::{PageContent, PageIterator};
+pub use region::Region;
+pub use serie::{Kind, Serie, SerieID, Status};
 
 use selectors::NEXT_DATA_SELECTOR;
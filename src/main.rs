@@ -43,56 +43,350 @@
 
 // }}}
 
-use clap::Parser;
+use clap::{ArgEnum, Parser, Subcommand};
 use eyre::{ensure, eyre, Result, WrapErr};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use piconbiere::{fs, termio, Client, Media, MediaType, Serie, SerieID};
+use piconbiere::{
+    cbz,
+    config::{Config, Options},
+    directory, epub, fs, pdf, pipeline, sidecar, termio, Client, EncodedPage,
+    Media, MediaType, Metadata, OutputFormat, Serie, SerieID,
+};
 use std::{
     collections::{HashMap, HashSet},
-    io::{Cursor, Write},
+    env,
+    io::{self, IsTerminal, Write},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     thread,
 };
-use zip::{write::FileOptions, ZipWriter};
+
+/// Environment variable holding the user password.
+const PASSWORD_ENV: &str = "PICONBIERE_PASSWORD";
 
 fn main() -> Result<()> {
     let opts = Opts::parse();
-    let client = Client::new(opts.retry);
+    let config =
+        Config::load(opts.config.as_deref()).context("load configuration")?;
+    // Command-line options take precedence over the configuration.
+    let options_for =
+        |serie: Option<SerieID>| opts.options.clone().or(config.options(serie));
+
+    match opts.command {
+        Command::Download { serie, ref number } => {
+            let options = options_for(Some(serie));
+            let client = connect(&options)?;
+            download_serie(&client, &options, serie, number)
+                .with_context(|| format!("download serie {serie}"))
+        },
+        Command::List { serie, format } => {
+            let options = options_for(Some(serie));
+            let client = connect(&options)?;
+            let serie = Serie::new(&client, serie, options.media_type())
+                .context("get serie")?;
+            match format {
+                Format::Text => {
+                    list(
+                        &serie,
+                        &serie_directory(&options, &serie),
+                        options.output_format(),
+                    );
+                    Ok(())
+                },
+                Format::Json => print_json(&serie),
+            }
+        },
+        Command::Info { serie, format } => {
+            let options = options_for(Some(serie));
+            let client = connect(&options)?;
+            let serie = Serie::new(&client, serie, options.media_type())
+                .context("get serie")?;
+            match format {
+                Format::Text => {
+                    info(&serie, &options);
+                    Ok(())
+                },
+                Format::Json => print_json(&serie),
+            }
+        },
+        Command::Sync => sync(&opts.options, &config),
+        Command::Login {
+            ref cookies,
+            ref access_token,
+        } => login(
+            &options_for(None),
+            cookies.as_deref(),
+            access_token.as_deref(),
+        ),
+        Command::Logout => logout(&options_for(None)),
+    }
+}
+
+/// Returns a client for the configured website, with the saved session.
+///
+/// A saved session rejected by the website is forgotten. If a username is
+/// provided, log in (unless already logged in).
+fn connect(options: &Options) -> Result<Client> {
+    let session_path = session_path(options)?;
+    let guest =
+        Client::new(options.region(), options.base_url(), options.retry())
+            .with_delay(options.request_delay());
+    let mut client = guest
+        .clone()
+        .with_session(&session_path)
+        .context("restore session")?;
+
+    // The website may have revoked the session since it was saved.
+    if client.is_logged_in()
+        && !client.check_session().context("check session")?
+    {
+        termio::print_warn("saved session rejected by the website");
+        std::fs::remove_file(&session_path)
+            .with_context(|| format!("remove {}", session_path.display()))?;
+        client = guest;
+    }
+
+    if let Some(email) = options.user() {
+        if !client.is_logged_in() {
+            let password = read_password(options.password_file())
+                .context("read password")?;
+            client
+                .login(email, &password)
+                .with_context(|| format!("login as {email}"))?;
+            client.save_session(&session_path).context("save session")?;
+        }
+    }
+
+    Ok(client)
+}
 
-    // If a username is provided, try to login.
-    if let Some(ref email) = opts.user {
-        let password = rpassword::prompt_password("Your password: ")
-            .context("read password")?;
+/// Opens a new session and saves it.
+///
+/// The session is imported from the browser when `cookies` or `access_token`
+/// is provided, otherwise the configured user logs in.
+fn login(
+    options: &Options,
+    cookies: Option<&Path>,
+    access_token: Option<&str>,
+) -> Result<()> {
+    let session_path = session_path(options)?;
+    let mut client =
+        Client::new(options.region(), options.base_url(), options.retry())
+            .with_delay(options.request_delay());
+
+    if let Some(path) = cookies {
+        client = client.with_cookies_file(path).context("import cookies")?;
+        ensure!(
+            client.check_session().context("check session")?,
+            "no valid session cookie imported"
+        );
+    } else if let Some(token) = access_token {
+        client = client
+            .with_access_token(token)
+            .context("import access token")?;
+        ensure!(
+            client.check_session().context("check session")?,
+            "access token rejected by the website"
+        );
+    } else {
+        let email = options
+            .user()
+            .ok_or_else(|| eyre!("no user to login as (see --user)"))?;
+        let password =
+            read_password(options.password_file()).context("read password")?;
         client
             .login(email, &password)
             .with_context(|| format!("login as {email}"))?;
     }
+    client.save_session(&session_path).context("save session")?;
+
+    termio::print_ok("logged in");
+    Ok(())
+}
+
+/// Forgets the saved session.
+fn logout(options: &Options) -> Result<()> {
+    let session_path = session_path(options)?;
+    if session_path.is_file() {
+        std::fs::remove_file(&session_path)
+            .with_context(|| format!("remove {}", session_path.display()))?;
+    }
 
+    termio::print_ok("logged out");
+    Ok(())
+}
+
+/// Returns the path to the session file.
+fn session_path(options: &Options) -> Result<PathBuf> {
+    options
+        .session_file()
+        .ok_or_else(|| eyre!("cannot locate session file"))
+}
+
+/// Downloads the selected media of a serie (every media if none selected).
+fn download_serie(
+    client: &Client,
+    options: &Options,
+    serie_id: SerieID,
+    selection: &[u16],
+) -> Result<()> {
     // Fetch serie info and media list.
-    let serie =
-        Serie::new(&client, opts.serie, opts.r#type).context("get serie")?;
+    let serie = Serie::new(client, serie_id, options.media_type())
+        .context("get serie")?;
 
     // Create output directory, if necessary.
-    let destination = [opts.output, fs::sanitize_name(serie.title())]
-        .iter()
-        .collect::<PathBuf>();
+    let destination = serie_directory(options, &serie);
     fs::mkdir_p(&destination).context("create serie directory")?;
+    // Missing cover or metadata shouldn't prevent the download.
+    if let Err(err) = sidecar::update(client, &destination, &serie) {
+        termio::print_warn(&format!("update serie metadata: {err:#}"));
+    }
 
-    download(&client, &destination, &serie, opts.r#type, &opts.number)
-        .with_context(|| format!("download serie {}", opts.serie))?;
+    download(client, &destination, &serie, options, selection)
+}
+
+/// Returns the directory where the media of `serie` are stored.
+fn serie_directory(options: &Options, serie: &Serie) -> PathBuf {
+    [options.output(), fs::sanitize_name(serie.title())]
+        .iter()
+        .collect()
+}
+
+/// Downloads the new media of every serie listed in the configuration.
+fn sync(cli_options: &Options, config: &Config) -> Result<()> {
+    let series = config.series().context("list configured series")?;
+    ensure!(!series.is_empty(), "no serie configured");
+
+    let mut failures = 0_usize;
+    for serie_id in series {
+        let options = cli_options.clone().or(config.options(Some(serie_id)));
+        let res = connect(&options).and_then(|client| {
+            download_serie(&client, &options, serie_id, &[])
+        });
+        if let Err(err) = res {
+            termio::print_err(&format!("serie {serie_id}: {err:#}"));
+            failures += 1;
+        }
+    }
+    ensure!(failures == 0, "{failures} serie(s) failed to sync");
 
     Ok(())
 }
 
+/// Prints a table of the media of a serie.
+///
+/// Local presence is checked against the serie directory, without creating
+/// it.
+fn list(serie: &Serie, destination: &Path, format: OutputFormat) {
+    let mut media = serie.media().collect::<Vec<_>>();
+    media.sort_unstable_by_key(|media| media.number());
+
+    println!("NUMBER  PAGES  ACCESS           LOCAL  TITLE");
+    for media in media {
+        let page_count = media
+            .page_count()
+            .map_or_else(|| "?".to_owned(), |count| count.to_string());
+        let is_present = if media.is_present_at(destination, format) {
+            "yes"
+        } else {
+            "no"
+        };
+
+        println!(
+            "{:>6}  {:>5}  {:<15}  {:<5}  {}",
+            media.number(),
+            page_count,
+            media.access().to_string(),
+            is_present,
+            media.title()
+        );
+    }
+}
+
+/// Prints an overview of a serie.
+fn info(serie: &Serie, options: &Options) {
+    let available = serie.media().filter(|media| media.is_available()).count();
+
+    println!("ID:        {}", serie.id());
+    println!("Title:     {}", serie.title());
+    println!("Website:   {}", options.base_url());
+    if !serie.authors().is_empty() {
+        println!("Authors:   {}", serie.authors().join(", "));
+    }
+    if let Some(kind) = serie.kind() {
+        println!("Kind:      {kind}");
+    }
+    if let Some(status) = serie.status() {
+        println!("Status:    {status}");
+    }
+    if !serie.genres().is_empty() {
+        println!("Genres:    {}", serie.genres().join(", "));
+    }
+    println!("Type:      {}", options.media_type());
+    println!("Media:     {}", serie.media_count());
+    println!("Available: {available}");
+    if let Some(description) = serie.description() {
+        println!("\n{description}");
+    }
+}
+
+/// Prints a serie, with its media, as JSON.
+fn print_json(serie: &Serie) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, serie)
+        .context("serialize serie")?;
+    writeln!(stdout).context("write to stdout")
+}
+
+/// Reads the user password.
+///
+/// By order of precedence, the password comes from:
+/// - the password file, if any
+/// - the `PICONBIERE_PASSWORD` environment variable
+/// - the standard input, if it's not a terminal
+/// - an interactive prompt
+fn read_password(password_file: Option<&Path>) -> Result<String> {
+    let password = if let Some(path) = password_file {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("read {}", path.display()))?;
+        first_line(&content)
+    } else if let Ok(password) = env::var(PASSWORD_ENV) {
+        password
+    } else if io::stdin().is_terminal() {
+        rpassword::prompt_password("Your password: ")
+            .context("prompt password")?
+    } else {
+        let mut content = String::new();
+        io::stdin()
+            .read_line(&mut content)
+            .context("read password from stdin")?;
+        first_line(&content)
+    };
+    ensure!(!password.is_empty(), "empty password");
+
+    Ok(password)
+}
+
+/// Returns the first line of `content`, without its line ending.
+fn first_line(content: &str) -> String {
+    content.lines().next().unwrap_or_default().to_owned()
+}
+
 fn download(
     client: &Client,
     destination: &Path,
     serie: &Serie,
-    media_type: MediaType,
+    options: &Options,
     selection: &[u16],
 ) -> Result<()> {
-    let media_list =
-        compute_media_list(serie.media(), media_type, selection, destination)?;
+    let media_type = options.media_type();
+    let media_list = compute_media_list(
+        serie.media(),
+        media_type,
+        options.output_format(),
+        selection,
+        destination,
+    )?;
 
     if media_list.is_empty() {
         return Ok(());
@@ -108,87 +402,167 @@ fn download(
             .progress_chars("##-"),
     );
     media_pb.set_message(media_type.to_string());
-    let page_pb = progress_bars.add(ProgressBar::new(
-        media_list
-            .iter()
-            .map(|media| u64::from(media.page_count()))
-            .sum(),
-    ));
-    setup_page_progress_bar(&page_pb);
+    // One page progress bar per worker, reused from one media to the next.
+    let workers = options.media_workers().min(media_list.len());
+    let page_pbs = (0..workers)
+        .map(|_| {
+            let page_pb = progress_bars.add(ProgressBar::new(0));
+            setup_page_progress_bar(&page_pb);
+            page_pb
+        })
+        .collect::<Vec<_>>();
     thread::spawn(move || {
         // Must be spawned in a dedicated thread to move forward/update.
         progress_bars.join().expect("wait for progress bars");
     });
 
     // Download every page of every (available) media o/
-    for media in media_list {
-        download_pages(client, media, destination, &page_pb)
-            .with_context(|| format!("download {}", media.title()))?;
-        media_pb.inc(1);
-    }
+    let next_media = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let res = thread::scope(|scope| {
+        let handles = page_pbs
+            .iter()
+            .map(|page_pb| {
+                scope.spawn(|| {
+                    // Stop picking new media as soon as one failed.
+                    while !failed.load(Ordering::Relaxed) {
+                        let index = next_media.fetch_add(1, Ordering::Relaxed);
+                        let Some(media) = media_list.get(index) else {
+                            break;
+                        };
+                        page_pb.reset();
+                        page_pb.set_length(
+                            media.page_count().map_or(0, u64::from),
+                        );
+                        page_pb.set_message(format!(
+                            "{media_type} {}",
+                            media.number()
+                        ));
+
+                        let metadata = Metadata::new(
+                            client,
+                            serie,
+                            media,
+                            options.direction(serie.kind()),
+                        );
+                        download_pages(
+                            client,
+                            media,
+                            &metadata,
+                            options,
+                            destination,
+                            page_pb,
+                        )
+                        .with_context(|| format!("download {}", media.title()))
+                        .inspect_err(|_| {
+                            failed.store(true, Ordering::Relaxed);
+                        })?;
+                        media_pb.inc(1);
+                    }
+
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().try_for_each(|handle| {
+            handle.join().expect("media worker panicked")
+        })
+    });
 
-    page_pb.finish();
+    for page_pb in page_pbs {
+        page_pb.finish();
+    }
     media_pb.finish();
 
-    Ok(())
+    res
 }
 
-/// Downloads the specified media pages as CBZ.
+/// Downloads the specified media pages, in the configured format.
 fn download_pages(
     client: &Client,
     media: &Media,
-    directory: &Path,
+    metadata: &Metadata<'_>,
+    options: &Options,
+    destination: &Path,
     progress_bar: &ProgressBar,
 ) -> Result<()> {
-    let title = media.title();
-    let mut buf = Vec::new();
-
-    // Download every image and make a CBZ out of them, all in-memory.
-    {
-        let mut cbz = ZipWriter::new(Cursor::new(&mut buf));
-        let options = FileOptions::default();
-
-        // Add the media directory in the archive.
-        cbz.add_directory(title, options)
-            .context("create media directory")?;
-
-        // XXX: we can use enumerate because the pages are sorted.
-        for (i, page) in media.fetch_pages(client.clone())?.enumerate() {
-            let filename = format!("{:03}.webp", i);
-            let page =
-                page.with_context(|| format!("fetch page {}", filename))?;
-
-            // Encode the image as lossless WebP.
-            let encoder = webp::Encoder::from_image(&page)
-                .map_err(|err| eyre!("encode {}: {}", filename, err))?;
-            let bytes = encoder.encode_lossless();
-
-            // Add the page in the archive.
-            cbz.start_file(&format!("{title}/{filename}"), options)
-                .with_context(|| format!("add image {}", filename))?;
-            cbz.write_all(&bytes)
-                .with_context(|| format!("write image {}", filename))?;
-
-            progress_bar.inc(1);
-        }
-        cbz.finish().expect("close in-memory zip");
+    let (format, encoder) = (options.output_format(), options.page_encoder());
+    // Processed pages are kept until the book is saved, to resume on failure.
+    let staging = media.staging_path(destination);
+    let pages = media
+        .fetch_pages(client.clone())?
+        .passthrough(options.passthrough())
+        .workers(options.page_workers())
+        .staging(staging.clone());
+    // Page count may only be known now.
+    if media.page_count().is_none() {
+        progress_bar.inc_length(pages.len() as u64);
     }
 
-    // Atomic write of the CBZ.
-    let path = [directory, media.filename().as_path()]
+    let path = [destination, media.filename(format).as_path()]
         .into_iter()
         .collect::<PathBuf>();
-    fs::atomic_write(&path, &buf).context("save CBZ")
+    // Pages are downloaded and encoded in background, while we write them.
+    let pages = if format == OutputFormat::Pdf {
+        pipeline::encode(pages, pdf::encode)
+    } else {
+        pipeline::encode(pages, move |page| encoder.encode(page))
+    };
+    let pages = pages.inspect(|_| progress_bar.inc(1));
+
+    save_book(format, &path, metadata, pages)?;
+
+    // The book is complete, staged pages are useless now.
+    if let Err(err) = std::fs::remove_dir_all(&staging) {
+        termio::print_warn(&format!(
+            "remove staging directory {}: {err}",
+            staging.display()
+        ));
+    }
+    // Only succeeds if no other media is staged.
+    if let Some(parent) = staging.parent() {
+        std::fs::remove_dir(parent).ok();
+    }
+
+    Ok(())
+}
+
+/// Makes a book out of the pages, and saves it at `path`.
+fn save_book(
+    format: OutputFormat,
+    path: &Path,
+    metadata: &Metadata<'_>,
+    pages: impl Iterator<Item = Result<EncodedPage>>,
+) -> Result<()> {
+    // Built in-memory, unless streamed to disk.
+    let buf = match format {
+        OutputFormat::Directory => {
+            return directory::write(path, pages).context("save pages");
+        },
+        OutputFormat::Cbz => {
+            let file = fs::AtomicFile::create(path).context("create CBZ")?;
+            return cbz::write(file, metadata, pages)
+                .context("build CBZ")?
+                .commit()
+                .context("save CBZ");
+        },
+        OutputFormat::Epub => epub::build(metadata, pages),
+        OutputFormat::Pdf => pdf::build(metadata, pages),
+    }
+    .with_context(|| format!("build {format}"))?;
+
+    // Atomic write of the book.
+    fs::atomic_write(path, &buf).with_context(|| format!("save {format}"))
 }
 
-/// Configures the progress bar for the pages.
+/// Configures the progress bar for the pages of a media.
 fn setup_page_progress_bar(progress_bar: &ProgressBar) {
     progress_bar.set_style(
         ProgressStyle::default_bar()
             .template("{msg:10}    [{bar:40.cyan/blue}] {pos:>4}/{len:4} ETA: {eta_precise}")
             .progress_chars("##-"),
     );
-    progress_bar.set_message("pages");
 }
 
 /// Computes the list of media to download.
@@ -198,6 +572,7 @@ fn setup_page_progress_bar(progress_bar: &ProgressBar) {
 fn compute_media_list<'a>(
     media: impl Iterator<Item = &'a Media>,
     media_type: MediaType,
+    format: OutputFormat,
     selection: &[u16],
     destination: &Path,
 ) -> Result<Vec<&'a Media>> {
@@ -227,7 +602,7 @@ fn compute_media_list<'a>(
             if !(selection.is_empty() || selection.contains(&media.number())) {
                 return false;
             }
-            if media.is_present_at(destination) {
+            if media.is_present_at(destination, format) {
                 termio::print_ok(&format!(
                     "{media_type} {} already downloaded",
                     media.number()
@@ -236,8 +611,9 @@ fn compute_media_list<'a>(
             }
             if !media.is_available() {
                 termio::print_warn(&format!(
-                    "{media_type} {} not available",
-                    media.number()
+                    "{media_type} {} not available ({})",
+                    media.number(),
+                    media.access()
                 ));
                 return false;
             }
@@ -251,27 +627,76 @@ fn compute_media_list<'a>(
 #[derive(Parser)]
 #[clap(author, version, about)]
 pub struct Opts {
-    /// Path to the output directory.
-    #[clap(short, long, default_value = ".")]
-    output: PathBuf,
-
-    /// Serie ID.
-    #[clap(short, long)]
-    serie: SerieID,
+    #[clap(subcommand)]
+    command: Command,
 
-    /// Episode or volume number.
-    #[clap(short, long)]
-    number: Vec<u16>,
+    #[clap(flatten)]
+    options: Options,
 
-    /// Media type to download.
-    #[clap(short, long = "type", arg_enum, value_parser, default_value_t = MediaType::Episode)]
-    r#type: MediaType,
+    /// Path to the configuration file.
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+}
 
-    /// Email to login.
-    #[clap(short, long)]
-    user: Option<String>,
+/// Available commands.
+#[derive(Subcommand)]
+enum Command {
+    /// Download the media of a serie.
+    Download {
+        /// Serie ID.
+        #[clap(short, long)]
+        serie: SerieID,
+
+        /// Episode or volume number (every media if not specified).
+        #[clap(short, long)]
+        number: Vec<u16>,
+    },
+
+    /// List the media of a serie.
+    List {
+        /// Serie ID.
+        #[clap(short, long)]
+        serie: SerieID,
+
+        /// Output format.
+        #[clap(long, arg_enum, value_parser, default_value = "text")]
+        format: Format,
+    },
+
+    /// Show an overview of a serie.
+    Info {
+        /// Serie ID.
+        #[clap(short, long)]
+        serie: SerieID,
+
+        /// Output format.
+        #[clap(long, arg_enum, value_parser, default_value = "text")]
+        format: Format,
+    },
+
+    /// Download the new media of every serie in the configuration file.
+    Sync,
+
+    /// Log in and save the session for the following runs.
+    Login {
+        /// Path to a cookies.txt file to import the session from.
+        #[clap(long, conflicts_with = "user")]
+        cookies: Option<PathBuf>,
+
+        /// Session token (value of the `access_token` cookie) to use.
+        #[clap(long, conflicts_with_all = &["user", "cookies"])]
+        access_token: Option<String>,
+    },
+
+    /// Forget the saved session.
+    Logout,
+}
 
-    /// Max number of retry for HTTP requests.
-    #[clap(long, default_value_t = 3)]
-    retry: u8,
+/// Output format of the read-only commands.
+#[derive(Clone, Copy, ArgEnum)]
+enum Format {
+    /// Human-readable output.
+    Text,
+    /// Machine-readable output.
+    Json,
 }
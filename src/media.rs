@@ -1,10 +1,9 @@
-use crate::{fs, models, Client, PageIterator, SerieID, NEXT_DATA_SELECTOR};
+use crate::{fs, models, Client, OutputFormat, PageIterator, SerieID};
 use clap::ArgEnum;
-use eyre::{bail, ensure, eyre, Result, WrapErr};
-use kuchiki::traits::*;
+use eyre::{bail, ensure, Result, WrapErr};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     fmt,
     path::{Path, PathBuf},
@@ -16,10 +15,14 @@ use url::Url;
 pub static EPISODE_TITLE_PREFIX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^#\d+ ").expect("invalid episode title prefix"));
 
+/// Name of the directory holding the pages of incomplete media.
+pub const STAGING_DIRECTORY: &str = ".staging";
+
 // -----------------------------------------------------------------------------
 
 /// Media access type.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AccessType {
     /// Free media, anyone can read it.
     Free,
@@ -48,16 +51,34 @@ impl FromStr for AccessType {
     }
 }
 
+impl fmt::Display for AccessType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Free => "free",
+                Self::TemporaryFree => "unlocked",
+                Self::WaitUntilFree => "wait until free",
+                Self::Paywalled => "paywalled",
+                Self::Paid => "paid",
+            }
+        )
+    }
+}
+
 // -----------------------------------------------------------------------------
 
 /// Type of media.
-#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, ArgEnum, Deserialize, Serialize,
+)]
 pub enum MediaType {
     /// An episode or a chapter of the serie.
-    #[serde(rename = "E")]
+    #[serde(rename(deserialize = "E", serialize = "episode"))]
     Episode,
     /// A complete volume of the serie.
-    #[serde(rename = "V")]
+    #[serde(rename(deserialize = "V", serialize = "volume"))]
     Volume,
 }
 
@@ -77,28 +98,73 @@ impl fmt::Display for MediaType {
 // -----------------------------------------------------------------------------
 
 /// A media (an episode or a volume).
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Media {
     /// Title.
     title: String,
     /// Media ID
     id: MediaID,
     /// Serie ID.
+    #[serde(skip)]
     serie_id: SerieID,
+    /// Media type.
+    #[serde(rename = "type")]
+    media_type: MediaType,
     /// Number in the serie.
     number: u16,
     /// Access type.
     access: AccessType,
-    /// Number of pages.
-    page_count: u16,
+    /// Number of pages, if known before opening the viewer.
+    page_count: Option<u16>,
 }
 
 impl Media {
+    /// Initializes a new media.
+    pub(crate) fn new(
+        media_type: MediaType,
+        id: MediaID,
+        serie_id: SerieID,
+        number: u16,
+        title: &str,
+        access: AccessType,
+        page_count: Option<u16>,
+    ) -> Self {
+        let title = match media_type {
+            MediaType::Episode => {
+                if title.is_empty() {
+                    format!("Episode {:03}", number)
+                } else {
+                    format!(
+                        "{:03} - {}",
+                        number,
+                        EPISODE_TITLE_PREFIX.replace(title, "")
+                    )
+                }
+            },
+            MediaType::Volume => format!("Tome {:02}", number),
+        };
+
+        Self {
+            title,
+            id,
+            serie_id,
+            media_type,
+            number,
+            access,
+            page_count,
+        }
+    }
+
     /// Returns the episode ID.
     pub fn id(&self) -> MediaID {
         self.id
     }
 
+    /// Returns the media type.
+    pub fn media_type(&self) -> MediaType {
+        self.media_type
+    }
+
     /// Returns the episode number.
     pub fn number(&self) -> u16 {
         self.number
@@ -109,8 +175,13 @@ impl Media {
         &self.title
     }
 
-    /// Returns the number of pages.
-    pub fn page_count(&self) -> u16 {
+    /// Returns the access type.
+    pub fn access(&self) -> AccessType {
+        self.access
+    }
+
+    /// Returns the number of pages, if known.
+    pub fn page_count(&self) -> Option<u16> {
         self.page_count
     }
 
@@ -122,65 +193,76 @@ impl Media {
         )
     }
 
-    /// Tests if the media is already present on disk.
-    pub fn is_present_at(&self, path: &Path) -> bool {
-        let filepath = [path, &self.filename()].iter().collect::<PathBuf>();
+    /// Tests if the media is already present on disk, in the given format.
+    ///
+    /// Media directories only exist once complete.
+    pub fn is_present_at(&self, path: &Path, format: OutputFormat) -> bool {
+        let filepath =
+            [path, &self.filename(format)].iter().collect::<PathBuf>();
+
+        match format {
+            OutputFormat::Directory => filepath.is_dir(),
+            OutputFormat::Cbz | OutputFormat::Epub | OutputFormat::Pdf => {
+                filepath.is_file()
+            },
+        }
+    }
 
-        filepath.is_file()
+    /// Returns the directory where the pages are kept until the media is
+    /// complete, under the serie directory `path`.
+    ///
+    /// It's keyed by media ID, thus survives renaming.
+    pub fn staging_path(&self, path: &Path) -> PathBuf {
+        [
+            path,
+            Path::new(STAGING_DIRECTORY),
+            Path::new(&self.id.to_string()),
+        ]
+        .iter()
+        .collect()
     }
 
-    /// Returns the media filename.
-    pub fn filename(&self) -> PathBuf {
-        let mut filename = fs::sanitize_name(self.title());
-        filename.set_extension("cbz");
-        filename
+    /// Returns the media filename (or directory name), for the given format.
+    pub fn filename(&self, format: OutputFormat) -> PathBuf {
+        // Don't use `set_extension`, titles may contain dots.
+        let mut filename = fs::sanitize_name(self.title()).into_os_string();
+        if let Some(extension) = format.extension() {
+            filename.push(".");
+            filename.push(extension);
+        }
+        filename.into()
     }
 
     /// Retrieves pages info and return a page iterator
     pub fn fetch_pages(&self, client: Client) -> Result<PageIterator> {
-        // Fetch the viewer page.
-        let html = client
-            .get_html(&self.viewer_url())
-            .context("get viewer page")?;
-
-        // Extract and parse the JSON payload.
-        let payload = NEXT_DATA_SELECTOR
-            .filter(html.descendants().elements())
-            .next()
-            .ok_or_else(|| eyre!("look for PageIteratorepisode __NEXT_DATA__"))?
-            .text_contents();
-        let data = serde_json::from_str::<models::viewer::NextData>(&payload)
-            .context("parse episode __NEXT_DATA__")?
-            .props
-            .page_props
-            .initial_state
-            .viewer
-            .p_data;
+        let viewer_url = self.viewer_url(&client);
+        let viewer = client
+            .region()
+            .get_viewer(&client, &viewer_url)
+            .context("get viewer")?;
 
         // Make sure we got the expected number of pages!
-        ensure!(
-            data.img.len() == usize::from(self.page_count),
-            "expected {} page, got {}",
-            self.page_count,
-            data.img.len(),
-        );
+        if let Some(page_count) = self.page_count {
+            ensure!(
+                viewer.pages.len() == usize::from(page_count),
+                "expected {} page, got {}",
+                page_count,
+                viewer.pages.len(),
+            );
+        }
 
         // Return the iterator to download the images.
-        let pages = data
-            .img
-            .into_iter()
-            .map(|img| img.path.try_into())
-            .collect::<Result<Vec<_>, _>>()
-            .context("invalid page URL")?;
-        Ok(PageIterator::new(client, pages, data.is_scrambled))
-    }
-
-    fn viewer_url(&self) -> Url {
-        Url::parse(&format!(
-            "https://piccoma.com/fr/viewer/{}/{}",
-            self.serie_id, self.id,
+        Ok(PageIterator::new(
+            client,
+            viewer_url,
+            viewer.pages,
+            viewer.is_scrambled,
         ))
-        .expect("valid media URL")
+    }
+
+    /// Returns the URL of the media viewer.
+    fn viewer_url(&self, client: &Client) -> Url {
+        client.url(&format!("viewer/{}/{}", self.serie_id, self.id))
     }
 }
 
@@ -192,36 +274,23 @@ impl TryFrom<models::serie::Media> for Media {
             MediaType::Episode => value.order_value,
             MediaType::Volume => value.volume,
         };
-        let title = match value.media_type {
-            MediaType::Episode => {
-                if value.title.is_empty() {
-                    format!("Episode {:03}", number)
-                } else {
-                    format!(
-                        "{:03} - {}",
-                        number,
-                        EPISODE_TITLE_PREFIX.replace(&value.title, "")
-                    )
-                }
-            },
-            MediaType::Volume => format!("Tome {:02}", number),
-        };
 
-        Ok(Self {
-            title,
-            id: value.id.into(),
-            serie_id: value.product_id.into(),
-            access: value.use_type.parse().context("parse access type")?,
+        Ok(Self::new(
+            value.media_type,
+            value.id.into(),
+            value.product_id.into(),
             number,
-            page_count: value.page_count,
-        })
+            &value.title,
+            value.use_type.parse().context("parse access type")?,
+            Some(value.page_count),
+        ))
     }
 }
 
 // -----------------------------------------------------------------------------
 
 /// Media ID on Piccoma.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
 pub struct MediaID(u32);
 
 impl fmt::Display for MediaID {
@@ -0,0 +1,13 @@
+//! Mininal model of the data returned by `/api/auth/signin`.
+
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SigninResponse {
+    // Error code, on failure.
+    #[serde(default)]
+    pub code: Option<String>,
+    // Error message, on failure.
+    #[serde(default)]
+    pub message: Option<String>,
+}
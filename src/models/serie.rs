@@ -12,8 +12,33 @@ pub struct Data {
 
 #[derive(Debug, Deserialize)]
 pub struct Product {
+    // Serie ID.
+    pub id: u32,
     // Title
     pub title: String,
+    // Authors (writer, artist, ...).
+    #[serde(default)]
+    pub author_list: Vec<Name>,
+    // Synopsis.
+    #[serde(default)]
+    pub description: Option<String>,
+    // Genres.
+    #[serde(default)]
+    pub genre_list: Vec<Name>,
+    // Cover image, may be relative.
+    #[serde(default)]
+    pub thumbnail_path: Option<String>,
+    // Completion status.
+    #[serde(default)]
+    pub is_completed: Option<bool>,
+    // Product kind (manga or smartoon).
+    #[serde(default)]
+    pub product_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Name {
+    pub name: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1,50 +1,54 @@
-use crate::Client;
+use crate::{client, format, Client, Region};
 use braque::{scramble, BlockSize};
 use eyre::{eyre, Result, WrapErr};
-use image::{io::Reader as ImageReader, DynamicImage};
-use once_cell::sync::Lazy;
-use regex::Regex;
-use std::{borrow::Cow, io::Cursor};
+use image::{
+    io::Reader as ImageReader, DynamicImage, ImageFormat, ImageOutputFormat,
+};
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    io::Cursor,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread,
+};
 use url::Url;
 
-/// Match the page number in the URL.
-pub static PAGE_NUMBER: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"i0*(?P<number>[0-9]+)\..{3,4}$")
-        .expect("invalid page number regex")
-});
-
 /// An episode page.
+#[derive(Clone)]
 pub struct Page {
     /// Image URL.
     url: Url,
     /// Image number in the episode.
     number: u16,
+    /// Website the page comes from.
+    region: Region,
 }
 
-// https://cdn.fr.piccoma.com/308/9957/eeQoB4cdy4szeVhesSEpa2Sf7J9yoJM5dWFB5Zc/i00016.jpg?credential=\u0026expires=1656892800\u0026signature=YnD0viyplpST8e25GxQDzoirnKI%3D\u0026q=Q9IXT44J6FDRRZB3KFSBJ7
-
-impl TryFrom<Url> for Page {
-    type Error = eyre::Report;
-
-    fn try_from(value: Url) -> Result<Self, Self::Error> {
-        let captures = PAGE_NUMBER
-            .captures(value.path())
-            .ok_or_else(|| eyre!("page number not found"))?;
-        let number = captures
-            .name("number")
-            .expect("capture group 'number'")
-            .as_str()
-            .parse()
-            .expect("valid number"); // must be valid thanks to regex.
+impl Page {
+    /// Initializes a new page.
+    pub(crate) fn new(region: Region, url: Url, number: u16) -> Self {
+        Self {
+            url,
+            number,
+            region,
+        }
+    }
 
-        Ok(Self { url: value, number })
+    /// Returns the image URL.
+    pub(crate) fn url(&self) -> &Url {
+        &self.url
     }
-}
 
-impl Page {
     /// Compute the page's scrambling seed.
     fn compute_seed(&self) -> Result<Vec<u8>> {
-        let mut key = self.get_key().ok_or_else(|| eyre!("get key"))?;
+        let mut key = self
+            .region
+            .scrambling_key(&self.url)
+            .ok_or_else(|| eyre!("get key"))?;
         let pivot = self.compute_pivot(key.len()).context("compute pivot")?;
 
         // Split the key at `pivot` and stitch it back to get the seed.
@@ -54,15 +58,6 @@ impl Page {
         Ok(seed)
     }
 
-    /// Returns the scrambling base key.
-    fn get_key(&self) -> Option<Vec<u8>> {
-        // Provided through the parameter `q`.
-        let q = Cow::from("q");
-        self.url.query_pairs().find_map(|(key, value)| {
-            (key == q).then(|| value.into_owned().into_bytes())
-        })
-    }
-
     /// Computes and returns the key's pivot.
     fn compute_pivot(&self, keylen: usize) -> Result<usize> {
         // "checksum" provided through the parameter `expires`.
@@ -72,11 +67,11 @@ impl Page {
         let checksum = self
             .url
             .query_pairs()
-            .find_map(|(key, value)| (key == expires).then(|| value))
+            .find_map(|(key, value)| (key == expires).then_some(value))
             .and_then(|checksum| {
-                checksum.chars().fold(Some(0), |acc, ch| {
-                    ch.to_digit(10).and_then(|x| acc.map(|sum| sum + x))
-                })
+                checksum
+                    .chars()
+                    .try_fold(0, |sum, ch| ch.to_digit(10).map(|x| sum + x))
             })
             .ok_or_else(|| eyre!("invalid checksum"))?;
 
@@ -89,82 +84,446 @@ impl Page {
     }
 }
 
+/// Content of a downloaded page.
+#[derive(Debug)]
+pub enum PageContent {
+    /// Decoded image, unscrambled if necessary.
+    Image(DynamicImage),
+    /// Image file, exactly as served by the website.
+    Original {
+        /// File content.
+        bytes: Vec<u8>,
+        /// Image format.
+        format: ImageFormat,
+    },
+}
+
+impl PageContent {
+    /// Returns the page dimensions, without decoding the image.
+    pub fn dimensions(&self) -> Result<(u32, u32)> {
+        match *self {
+            Self::Image(ref image) => Ok((image.width(), image.height())),
+            Self::Original { ref bytes, format } => {
+                ImageReader::with_format(Cursor::new(bytes), format)
+                    .into_dimensions()
+                    .context("read image dimensions")
+            },
+        }
+    }
+
+    /// Returns the decoded image.
+    pub fn to_image(&self) -> Result<Cow<'_, DynamicImage>> {
+        match *self {
+            Self::Image(ref image) => Ok(Cow::Borrowed(image)),
+            Self::Original { ref bytes, format } => {
+                ImageReader::with_format(Cursor::new(bytes), format)
+                    .decode()
+                    .map(Cow::Owned)
+                    .context("decode image")
+            },
+        }
+    }
+
+    /// Consumes the page and returns the decoded image.
+    pub fn into_image(self) -> Result<DynamicImage> {
+        match self {
+            Self::Image(image) => Ok(image),
+            Self::Original { .. } => self.to_image().map(Cow::into_owned),
+        }
+    }
+}
+
 /// Iterator on an episode's pages.
+///
+/// Pages are downloaded (and fixed) by a pool of workers, but yielded in
+/// order.
 pub struct PageIterator {
-    /// Client to retrieve the pages.
-    client: Client,
-    /// Are those page scrambled?
-    use_scrambling: bool,
-    /// Pages list, correctly ordered.
+    /// Downloads and fixes the pages.
+    fetcher: Fetcher,
+    /// Number of pages processed concurrently.
+    workers: usize,
+    /// Pages not scheduled yet, correctly ordered.
     pages: Vec<Page>,
-    /// Scrambling block size.
-    block_size: BlockSize,
-    /// Reusable buffer to download the images.
-    buffer: Vec<u8>,
+    /// Number of pages scheduled so far.
+    scheduled: usize,
+    /// Number of pages yielded so far.
+    yielded: usize,
+    /// Worker pool, started on the first page.
+    pool: Option<Pool>,
+    /// Pages processed ahead of their turn.
+    pending: BTreeMap<usize, Result<PageContent>>,
 }
 
 impl PageIterator {
-    pub fn new(
+    pub(crate) fn new(
         client: Client,
+        viewer_url: Url,
         mut pages: Vec<Page>,
         use_scrambling: bool,
     ) -> Self {
         // Make sure the pages are correctly ordered.
         // i.e. from last to first, since we iter/pop from the end.
-        pages.sort_unstable_by(|a, b| b.number.cmp(&a.number));
+        pages.sort_unstable_by_key(|page| Reverse(page.number));
 
         Self {
-            client,
-            use_scrambling,
+            fetcher: Fetcher {
+                client,
+                use_scrambling,
+                passthrough: false,
+                staging: None,
+                refresher: Arc::new(Refresher {
+                    viewer_url,
+                    urls: Mutex::default(),
+                }),
+                // Block size is constant across the whole website (for now...)
+                block_size: BlockSize::try_from(50).expect("valid block size"),
+            },
+            workers: 1,
             pages,
-            // Block size is constant across the whole website (for now...)
-            block_size: BlockSize::try_from(50).expect("valid block size"),
-            buffer: Vec::new(),
+            scheduled: 0,
+            yielded: 0,
+            pool: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Keeps the original files of the pages, when they aren't scrambled and
+    /// their format is widely supported.
+    #[must_use]
+    pub fn passthrough(mut self, enabled: bool) -> Self {
+        self.fetcher.passthrough = enabled;
+        self
+    }
+
+    /// Keeps the processed pages in the directory `path`, and reuses the ones
+    /// already there instead of downloading them again.
+    ///
+    /// This allows an interrupted download to resume where it stopped.
+    #[must_use]
+    pub fn staging(mut self, path: PathBuf) -> Self {
+        self.fetcher.staging = Some(path);
+        self
+    }
+
+    /// Sets the number of pages downloaded concurrently (at least one).
+    #[must_use]
+    pub fn workers(mut self, count: usize) -> Self {
+        self.workers = count.max(1);
+        self
+    }
+
+    /// Schedules pages, while keeping a bounded number of them in flight.
+    fn schedule(&mut self) {
+        let workers = self.workers.min(self.pages.len()).max(1);
+        let fetcher = &self.fetcher;
+        let pool = self
+            .pool
+            .get_or_insert_with(|| Pool::start(fetcher, workers));
+
+        // Enough to keep the workers busy, not too much to bound memory usage.
+        while self.scheduled < self.yielded + 2 * self.workers {
+            let page = match self.pages.pop() {
+                Some(page) => page,
+                None => break,
+            };
+            // Workers only stop when we do, can't fail.
+            let _ = pool.jobs.send((self.scheduled, page));
+            self.scheduled += 1;
         }
     }
 }
 
 impl Iterator for PageIterator {
-    type Item = Result<DynamicImage>;
+    type Item = Result<PageContent>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.pages.pop().map(|page| {
-            // Download the image.
-            self.buffer.clear();
-            self.client
-                .get_image(&page.url, &mut self.buffer)
-                .with_context(|| format!("download image from {}", page.url))?;
-
-            // Decode it.
-            let image = ImageReader::new(Cursor::new(&self.buffer))
-                .with_guessed_format()
-                .with_context(|| {
-                    format!("determine image format from {}", page.url)
-                })?
-                .decode()
-                .with_context(|| format!("decode image from {}", page.url))?;
-
-            // Fix scrambling if necessary.
-            if self.use_scrambling {
-                let seed = page.compute_seed().with_context(|| {
-                    format!("compute scrambling seed for {}", page.url)
-                })?;
-
-                return Ok(scramble(&image, self.block_size, &seed));
+        if self.len() == 0 {
+            return None;
+        }
+        self.schedule();
+        let pool = self.pool.as_ref().expect("started worker pool");
+
+        let page = loop {
+            if let Some(page) = self.pending.remove(&self.yielded) {
+                break page;
+            }
+            match pool.results.recv() {
+                Ok((index, page)) => {
+                    self.pending.insert(index, page);
+                },
+                Err(_) => break Err(eyre!("page workers stopped")),
             }
+        };
+        self.yielded += 1;
 
-            Ok(image)
-        })
+        Some(page)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.pages.len(), Some(self.pages.len()))
+        (self.len(), Some(self.len()))
     }
 }
 
 impl ExactSizeIterator for PageIterator {
     fn len(&self) -> usize {
-        self.pages.len()
+        self.pages.len() + self.scheduled - self.yielded
+    }
+}
+
+/// Downloads a page and fixes it, if necessary.
+#[derive(Clone)]
+struct Fetcher {
+    /// Client to retrieve the pages.
+    client: Client,
+    /// Are those page scrambled?
+    use_scrambling: bool,
+    /// Keep the original files of the unscrambled pages?
+    passthrough: bool,
+    /// Directory where the processed pages are kept, if any.
+    staging: Option<PathBuf>,
+    /// Provides fresh URLs, once the signed ones expire.
+    refresher: Arc<Refresher>,
+    /// Scrambling block size.
+    block_size: BlockSize,
+}
+
+impl Fetcher {
+    /// Retrieves `page`, from the staging directory if it's already there.
+    fn fetch(&self, page: &Page, buffer: &mut Vec<u8>) -> Result<PageContent> {
+        let staging = match self.staging {
+            Some(ref staging) => staging,
+            None => return self.download(page, buffer),
+        };
+
+        if let Some(content) = self.load(staging, page.number)? {
+            return Ok(content);
+        }
+        let content = self.download(page, buffer)?;
+        stage(staging, page.number, &content)?;
+
+        Ok(content)
+    }
+
+    /// Loads the page `number` from the staging directory, if present.
+    fn load(&self, staging: &Path, number: u16) -> Result<Option<PageContent>> {
+        // Fixed pages are stored losslessly, as PNG.
+        let path = staging.join(format!("{number:03}.png"));
+        if path.is_file() {
+            let image = image::open(&path)
+                .with_context(|| format!("load {}", path.display()))?;
+            return Ok(Some(PageContent::Image(image)));
+        }
+
+        for format in format::ORIGINAL_FORMATS {
+            let path = staging.join(original_filename(number, format));
+            if !path.is_file() {
+                continue;
+            }
+            let bytes = std::fs::read(&path)
+                .with_context(|| format!("load {}", path.display()))?;
+            let content = PageContent::Original { bytes, format };
+
+            // Settings may have changed since the page was staged.
+            return Ok(Some(if self.passthrough {
+                content
+            } else {
+                PageContent::Image(content.into_image()?)
+            }));
+        }
+
+        Ok(None)
+    }
+
+    /// Downloads `page`, using `buffer` as download buffer.
+    fn download(
+        &self,
+        page: &Page,
+        buffer: &mut Vec<u8>,
+    ) -> Result<PageContent> {
+        // Download the image, with a fresh URL if access is denied: signed
+        // URLs may have expired since the viewer was opened.
+        let (mut page, generation) = self.refresher.current(page);
+        buffer.clear();
+        let mut res = self.client.get_image(page.url(), buffer);
+        if matches!(res, Err(ref err) if client::is_forbidden(err)) {
+            page = self.refresher.refresh(&self.client, &page, generation)?;
+            buffer.clear();
+            res = self.client.get_image(page.url(), buffer);
+        }
+        res.with_context(|| format!("download image from {}", page.url))?;
+
+        let format = image::guess_format(buffer).with_context(|| {
+            format!("determine image format from {}", page.url)
+        })?;
+
+        // Keep the original file, when there is nothing to fix.
+        if self.passthrough
+            && !self.use_scrambling
+            && format::ORIGINAL_FORMATS.contains(&format)
+        {
+            return Ok(PageContent::Original {
+                bytes: mem::take(buffer),
+                format,
+            });
+        }
+
+        // Decode it.
+        let image = ImageReader::with_format(Cursor::new(&buffer), format)
+            .decode()
+            .with_context(|| format!("decode image from {}", page.url))?;
+
+        // Fix scrambling if necessary.
+        if self.use_scrambling {
+            let seed = page.compute_seed().with_context(|| {
+                format!("compute scrambling seed for {}", page.url)
+            })?;
+
+            return Ok(PageContent::Image(scramble(
+                &image,
+                self.block_size,
+                &seed,
+            )));
+        }
+
+        Ok(PageContent::Image(image))
+    }
+}
+
+/// Provides fresh page URLs, by opening the media viewer again.
+///
+/// Shared by the workers: URLs are refreshed once for all of them.
+struct Refresher {
+    /// URL of the media viewer.
+    viewer_url: Url,
+    /// Latest page URLs.
+    urls: Mutex<Urls>,
+}
+
+/// Page URLs, as of the latest refresh.
+#[derive(Default)]
+struct Urls {
+    /// Number of refreshes so far.
+    generation: u32,
+    /// Pages info, by page number (empty until the first refresh).
+    pages: HashMap<u16, Page>,
+}
+
+impl Refresher {
+    /// Returns `page` with its latest known URL, and the URL generation.
+    fn current(&self, page: &Page) -> (Page, u32) {
+        let urls = self.urls.lock().unwrap_or_else(PoisonError::into_inner);
+        let latest = urls.pages.get(&page.number).unwrap_or(page).clone();
+
+        (latest, urls.generation)
+    }
+
+    /// Returns `page` with a fresh URL, replacing the URLs of `generation`.
+    fn refresh(
+        &self,
+        client: &Client,
+        page: &Page,
+        generation: u32,
+    ) -> Result<Page> {
+        // Other workers wait for the refresh, instead of doing their own.
+        let mut urls = self.urls.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // URLs may have been refreshed while we were waiting.
+        if urls.generation == generation {
+            let viewer = client
+                .region()
+                .get_viewer(client, &self.viewer_url)
+                .with_context(|| {
+                format!("refresh URL of page {}", page.number)
+            })?;
+            urls.generation += 1;
+            urls.pages = viewer
+                .pages
+                .into_iter()
+                .map(|page| (page.number, page))
+                .collect();
+        }
+
+        urls.pages.get(&page.number).cloned().ok_or_else(|| {
+            eyre!("page {} missing from refreshed viewer", page.number)
+        })
+    }
+}
+
+/// Saves the page `number` in the staging directory.
+fn stage(staging: &Path, number: u16, content: &PageContent) -> Result<()> {
+    let (path, bytes) = match *content {
+        PageContent::Original { ref bytes, format } => (
+            staging.join(original_filename(number, format)),
+            Cow::Borrowed(bytes),
+        ),
+        PageContent::Image(ref image) => {
+            let mut bytes = Cursor::new(Vec::new());
+            image
+                .write_to(&mut bytes, ImageOutputFormat::Png)
+                .with_context(|| format!("encode page {number}"))?;
+            (
+                staging.join(format!("{number:03}.png")),
+                Cow::Owned(bytes.into_inner()),
+            )
+        },
+    };
+
+    crate::fs::mkdir_p(staging).context("create staging directory")?;
+    crate::fs::atomic_write(&path, &bytes)
+        .with_context(|| format!("stage page {number}"))
+}
+
+/// Returns the name of an original page file, in the staging directory.
+fn original_filename(number: u16, format: ImageFormat) -> String {
+    format!("{number:03}.original.{}", format::extension(format))
+}
+
+/// Pool of threads processing the pages.
+///
+/// Workers stop once the pool is dropped.
+struct Pool {
+    /// Pages to process, with their index.
+    jobs: mpsc::Sender<(usize, Page)>,
+    /// Processed pages, with their index, in completion order.
+    results: mpsc::Receiver<(usize, Result<PageContent>)>,
+}
+
+impl Pool {
+    /// Starts `count` workers processing pages with `fetcher`.
+    fn start(fetcher: &Fetcher, count: usize) -> Self {
+        let (jobs, job_queue) = mpsc::channel::<(usize, Page)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_queue = Arc::new(Mutex::new(job_queue));
+
+        for _ in 0..count {
+            let fetcher = fetcher.clone();
+            let job_queue = Arc::clone(&job_queue);
+            let result_sender = result_sender.clone();
+
+            thread::spawn(move || {
+                let mut buffer = Vec::new();
+                // The lock is released as soon as we have a job.
+                while let Ok(Ok((index, page))) =
+                    job_queue.lock().map(|queue| queue.recv())
+                {
+                    // A panicking worker must not leave its page missing.
+                    let page = panic::catch_unwind(AssertUnwindSafe(|| {
+                        fetcher.fetch(&page, &mut buffer)
+                    }))
+                    .unwrap_or_else(|_| {
+                        Err(eyre!(
+                            "process page {}: worker panicked",
+                            page.number
+                        ))
+                    });
+                    if result_sender.send((index, page)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self { jobs, results }
     }
 }
 
@@ -179,6 +538,7 @@ mod tests {
         let page = Page {
             url: Url::parse(url).expect("valid URL"),
             number: 0,
+            region: Region::Fr,
         };
         let expected = b"KR9FHBRB81GVIXIH7SKRE4";
 
@@ -194,6 +554,7 @@ mod tests {
         let page = Page {
             url: Url::parse(url).expect("valid URL"),
             number: 0,
+            region: Region::Fr,
         };
         let expected = b"IVEPVNF7KSBYZ4266A59RR";
 
@@ -209,6 +570,7 @@ mod tests {
         let page = Page {
             url: Url::parse(url).expect("valid URL"),
             number: 0,
+            region: Region::Fr,
         };
         let expected = b"TBSLV030DAZSA1PQ5I0CDC";
 
@@ -217,12 +579,28 @@ mod tests {
         assert_eq!(&res, expected);
     }
 
+    #[test]
+    fn compute_seed_jp() {
+        let url = "http://foo.com/f/32/1337/IH7SKRE4KR9FHBRB81GVIX/1.jpg?expires=1656547200";
+        let page = Page {
+            url: Url::parse(url).expect("valid URL"),
+            number: 0,
+            region: Region::Jp,
+        };
+        let expected = b"KR9FHBRB81GVIXIH7SKRE4";
+
+        let res = page.compute_seed().expect("seed");
+
+        assert_eq!(&res, expected);
+    }
+
     #[test]
     fn missing_key() {
         let url = "http://foo.com?expires=1656547200&p=PQ5I0CDCTBSLV030DAZSA1";
         let page = Page {
             url: Url::parse(url).expect("valid URL"),
             number: 0,
+            region: Region::Fr,
         };
 
         let res = page.compute_seed();
@@ -236,6 +614,7 @@ mod tests {
         let page = Page {
             url: Url::parse(url).expect("valid URL"),
             number: 0,
+            region: Region::Fr,
         };
 
         let res = page.compute_seed();
@@ -249,10 +628,29 @@ mod tests {
         let page = Page {
             url: Url::parse(url).expect("valid URL"),
             number: 0,
+            region: Region::Fr,
         };
 
         let res = page.compute_seed();
 
         assert!(res.is_err());
     }
+
+    #[test]
+    fn readable_originals() {
+        let image = DynamicImage::new_rgb8(12, 34);
+
+        // Originals must be readable, to be measured or fixed.
+        for format in format::ORIGINAL_FORMATS {
+            let mut bytes = Cursor::new(Vec::new());
+            image.write_to(&mut bytes, format).expect("encode original");
+            let content = PageContent::Original {
+                bytes: bytes.into_inner(),
+                format,
+            };
+
+            assert_eq!(content.dimensions().expect("dimensions"), (12, 34));
+            assert!(content.into_image().is_ok(), "decode {format:?}");
+        }
+    }
 }
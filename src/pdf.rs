@@ -0,0 +1,269 @@
+//! PDF builder.
+
+use crate::{format::Metadata, Direction, EncodedPage, PageContent};
+use eyre::{bail, ensure, Result, WrapErr};
+use image::{
+    codecs::jpeg::{JpegDecoder, JpegEncoder},
+    ColorType, DynamicImage, ImageDecoder, ImageFormat,
+};
+use std::{fmt::Write as _, io::Cursor};
+
+/// Quality of the JPEG-encoded pages.
+const JPEG_QUALITY: u8 = 90;
+
+/// Objects with a fixed number, written once every page is known.
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const OUTLINES: usize = 3;
+const OUTLINE_ITEM: usize = 4;
+const INFO: usize = 5;
+/// Number of the first page object.
+///
+/// Each page is made of 3 objects: the page itself, its content stream and
+/// its image.
+const FIRST_PAGE: usize = 6;
+
+/// Builds, in-memory, a PDF out of the media pages.
+///
+/// Each page is a JPEG image, as returned by [`encode`], on a page of the same
+/// size (one pixel per point). The document outline has a single entry, for
+/// the media.
+pub fn build(
+    metadata: &Metadata<'_>,
+    pages: impl Iterator<Item = Result<EncodedPage>>,
+) -> Result<Vec<u8>> {
+    let mut pdf = Writer::new();
+
+    // XXX: we can use enumerate because the pages are sorted.
+    let mut page_ids = Vec::new();
+    for (i, page) in pages.enumerate() {
+        let number = i + 1;
+        let page = page.with_context(|| format!("process page {number}"))?;
+        let (width, height) = page.dimensions;
+        let color_space = color_space(&page)
+            .with_context(|| format!("embed page {number}"))?;
+
+        let page_id = FIRST_PAGE + 3 * i;
+        let (content_id, image_id) = (page_id + 1, page_id + 2);
+        pdf.object(
+            page_id,
+            &format!(
+                "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {width} {height}] /Resources << /XObject << /Im0 {image_id} 0 R >> >> /Contents {content_id} 0 R >>"
+            ),
+        );
+        pdf.stream(
+            content_id,
+            "",
+            format!("q {width} 0 0 {height} 0 0 cm /Im0 Do Q").as_bytes(),
+        );
+        pdf.stream(
+            image_id,
+            &format!("/Type /XObject /Subtype /Image /Width {width} /Height {height} /ColorSpace /{color_space} /BitsPerComponent 8 /Filter /DCTDecode"),
+            &page.bytes,
+        );
+        page_ids.push(page_id);
+    }
+    ensure!(!page_ids.is_empty(), "no page");
+
+    let kids = page_ids.iter().fold(String::new(), |mut acc, id| {
+        let _ = write!(acc, "{id} 0 R ");
+        acc
+    });
+    let direction = match metadata.direction {
+        Direction::Rtl => "R2L",
+        Direction::Ltr => "L2R",
+    };
+    let title = text_string(metadata.title);
+
+    pdf.object(
+        CATALOG,
+        &format!("<< /Type /Catalog /Pages {PAGES} 0 R /Outlines {OUTLINES} 0 R /PageMode /UseOutlines /ViewerPreferences << /DisplayDocTitle true /Direction /{direction} >> >>"),
+    );
+    pdf.object(
+        PAGES,
+        &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.trim_end(),
+            page_ids.len()
+        ),
+    );
+    pdf.object(
+        OUTLINES,
+        &format!("<< /Type /Outlines /First {OUTLINE_ITEM} 0 R /Last {OUTLINE_ITEM} 0 R /Count 1 >>"),
+    );
+    pdf.object(
+        OUTLINE_ITEM,
+        &format!(
+            "<< /Title {title} /Parent {OUTLINES} 0 R /Dest [{FIRST_PAGE} 0 R /Fit] >>"
+        ),
+    );
+    let author = if metadata.authors.is_empty() {
+        String::new()
+    } else {
+        format!("/Author {} ", text_string(&metadata.authors.join(", ")))
+    };
+    pdf.object(
+        INFO,
+        &format!(
+            "<< /Title {title} {author}/Subject {} /Producer (piconbiere) >>",
+            text_string(metadata.serie)
+        ),
+    );
+
+    Ok(pdf.finish(CATALOG, INFO))
+}
+
+/// Encodes a page as a JPEG image, as expected by [`build`].
+///
+/// Original JPEG files are kept as-is, as long as their color space is a
+/// simple one.
+pub fn encode(page: PageContent) -> Result<EncodedPage> {
+    let dimensions = page.dimensions()?;
+    let page = match page {
+        PageContent::Original {
+            bytes,
+            format: ImageFormat::Jpeg,
+        } => {
+            let page = EncodedPage {
+                bytes,
+                format: ImageFormat::Jpeg,
+                dimensions,
+            };
+            if color_space(&page).is_ok() {
+                return Ok(page);
+            }
+
+            PageContent::Original {
+                bytes: page.bytes,
+                format: ImageFormat::Jpeg,
+            }
+        },
+        page => page,
+    };
+
+    let image = page.to_image()?;
+    let mut bytes = Vec::new();
+    JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+        .encode_image(&DynamicImage::ImageRgb8(image.to_rgb8()))?;
+
+    Ok(EncodedPage {
+        bytes,
+        format: ImageFormat::Jpeg,
+        dimensions,
+    })
+}
+
+/// Returns the color space of a JPEG page.
+fn color_space(page: &EncodedPage) -> Result<&'static str> {
+    ensure!(page.format == ImageFormat::Jpeg, "not a JPEG image");
+    let decoder = JpegDecoder::new(Cursor::new(&page.bytes))
+        .context("read JPEG header")?;
+
+    match decoder.color_type() {
+        ColorType::L8 => Ok("DeviceGray"),
+        ColorType::Rgb8 => Ok("DeviceRGB"),
+        color_type => bail!("unsupported color type {color_type:?}"),
+    }
+}
+
+/// Encodes `text` as a PDF text string (hexadecimal UTF-16BE, with BOM).
+fn text_string(text: &str) -> String {
+    text.encode_utf16()
+        .fold(String::from("<FEFF"), |mut acc, unit| {
+            let _ = write!(acc, "{unit:04X}");
+            acc
+        })
+        + ">"
+}
+
+/// Low-level PDF writer, keeping track of the objects offset.
+struct Writer {
+    /// Document content.
+    buffer: Vec<u8>,
+    /// Offset of each object, indexed by number.
+    offsets: Vec<Option<usize>>,
+}
+
+impl Writer {
+    /// Initializes a new document, with its header.
+    fn new() -> Self {
+        // The binary comment tells tools that the file isn't plain text.
+        let mut buffer = b"%PDF-1.7\n%".to_vec();
+        buffer.extend_from_slice(&[0xE2, 0xE3, 0xCF, 0xD3, b'\n']);
+
+        Self {
+            buffer,
+            offsets: vec![None],
+        }
+    }
+
+    /// Writes the object `id`.
+    fn object(&mut self, id: usize, content: &str) {
+        self.start_object(id);
+        self.buffer.extend_from_slice(content.as_bytes());
+        self.buffer.extend_from_slice(b"\nendobj\n");
+    }
+
+    /// Writes the stream object `id`, with the extra `entries` in its
+    /// dictionary.
+    fn stream(&mut self, id: usize, entries: &str, data: &[u8]) {
+        let separator = if entries.is_empty() { "" } else { " " };
+
+        self.start_object(id);
+        self.buffer.extend_from_slice(
+            format!(
+                "<< {entries}{separator}/Length {} >>\nstream\n",
+                data.len()
+            )
+            .as_bytes(),
+        );
+        self.buffer.extend_from_slice(data);
+        self.buffer.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    /// Records the offset of the object `id`, and writes its header.
+    fn start_object(&mut self, id: usize) {
+        if self.offsets.len() <= id {
+            self.offsets.resize(id + 1, None);
+        }
+        self.offsets[id] = Some(self.buffer.len());
+        self.buffer
+            .extend_from_slice(format!("{id} 0 obj\n").as_bytes());
+    }
+
+    /// Writes the cross-reference table and the trailer.
+    fn finish(mut self, root: usize, info: usize) -> Vec<u8> {
+        let xref_offset = self.buffer.len();
+        let size = self.offsets.len();
+
+        let mut xref = format!("xref\n0 {size}\n");
+        for offset in &self.offsets {
+            // Each entry must be exactly 20 bytes long.
+            let _ = match *offset {
+                Some(offset) => writeln!(xref, "{offset:010} 00000 n "),
+                None => writeln!(xref, "0000000000 65535 f "),
+            };
+        }
+        let _ = write!(
+            xref,
+            "trailer\n<< /Size {size} /Root {root} 0 R /Info {info} 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n"
+        );
+        self.buffer.extend_from_slice(xref.as_bytes());
+
+        self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_text_string() {
+        assert_eq!(
+            text_string("Tome 01"),
+            "<FEFF0054006F006D0065002000300031>"
+        );
+        assert_eq!(text_string("モック"), "<FEFF30E230C330AF>");
+    }
+}
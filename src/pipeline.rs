@@ -0,0 +1,71 @@
+//! Concurrent processing of the pages, in stages.
+//!
+//! Pages are fetched (downloaded, decoded and unscrambled) by the workers of
+//! a [`PageIterator`], then encoded in a dedicated thread, before being
+//! written by the caller. Stages are connected by bounded channels: encoding
+//! a page overlaps the download of the next ones, while memory usage stays
+//! bounded.
+
+use crate::{EncodedPage, PageContent, PageIterator};
+use eyre::{eyre, Result};
+use std::{sync::mpsc, thread};
+
+/// Number of pages buffered between two stages.
+const STAGE_CAPACITY: usize = 4;
+
+/// Encodes the pages yielded by `pages` with `encode`, in a dedicated thread.
+///
+/// Pages are yielded in order, and the processing stops as soon as the
+/// returned iterator is dropped.
+pub fn encode<F>(pages: PageIterator, encode: F) -> EncodedPages
+where
+    F: Fn(PageContent) -> Result<EncodedPage> + Send + 'static,
+{
+    let remaining = pages.len();
+    let (sender, receiver) = mpsc::sync_channel(STAGE_CAPACITY);
+
+    thread::spawn(move || {
+        for page in pages {
+            // Errors are forwarded, the writer decides what to do with them.
+            if sender.send(page.and_then(&encode)).is_err() {
+                break;
+            }
+        }
+    });
+
+    EncodedPages {
+        receiver,
+        remaining,
+    }
+}
+
+/// Iterator on the encoded pages of a media, in order.
+pub struct EncodedPages {
+    /// Output of the encoding stage.
+    receiver: mpsc::Receiver<Result<EncodedPage>>,
+    /// Number of pages not yielded yet.
+    remaining: usize,
+}
+
+impl Iterator for EncodedPages {
+    type Item = Result<EncodedPage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        Some(
+            self.receiver
+                .recv()
+                .unwrap_or_else(|_| Err(eyre!("encoding stage stopped"))),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for EncodedPages {}
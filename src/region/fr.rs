@@ -0,0 +1,259 @@
+//! French website: Next.js payloads and haribo API.
+
+use super::Viewer;
+use crate::{
+    models, page::Page, Client, LoginError, MediaType, Region, Serie, SerieID,
+    NEXT_DATA_SELECTOR,
+};
+use eyre::{eyre, Result, WrapErr};
+use kuchiki::traits::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use url::Url;
+
+/// Match the page number in the URL.
+static PAGE_NUMBER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"i0*(?P<number>[0-9]+)\..{3,4}$")
+        .expect("invalid page number regex")
+});
+
+/// Logs into the website using the specified credential.
+pub(super) fn login(
+    client: &Client,
+    email: &str,
+    password: &str,
+) -> Result<()> {
+    let response = client
+        .post_json(
+            &client.url("api/auth/signin"),
+            &ureq::json!({
+                "email": email,
+                "password": password,
+                "redirect": client.base_url().as_str(),
+            }),
+        )
+        .context("login")?;
+    let status = response.status();
+    let body = response.into_string().context("read login response")?;
+
+    match login_error(status, &body) {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// Extracts the reason of the login failure, if any, from the response.
+fn login_error(status: u16, body: &str) -> Option<LoginError> {
+    // Body may not be JSON, e.g. a redirection to the home page.
+    let response = serde_json::from_str::<models::auth::SigninResponse>(body)
+        .unwrap_or_default();
+    let code = response.code.unwrap_or_default().to_lowercase();
+
+    if code.contains("captcha") {
+        return Some(LoginError::CaptchaRequired);
+    }
+    if code.contains("lock") || status == 423 {
+        return Some(LoginError::AccountLocked);
+    }
+    if code.contains("credential") || code.contains("password") || status == 401
+    {
+        return Some(LoginError::BadCredentials);
+    }
+    if status >= 400 || !code.is_empty() {
+        return Some(LoginError::Rejected(
+            response
+                .message
+                .filter(|message| !message.is_empty())
+                .unwrap_or_else(|| format!("HTTP {status}")),
+        ));
+    }
+
+    None
+}
+
+/// Retrieves the serie info and its media list.
+pub(super) fn get_serie(
+    client: &Client,
+    id: SerieID,
+    media_type: MediaType,
+) -> Result<Serie> {
+    // We have two way of extracting the list of media:
+    // - the API
+    // - the embedded JSON payload
+    //
+    // API can only be used if you are logged in.
+    // Embedded JSON payload only contains unread media when you're
+    // logged in, otherwise it's complete.
+    //
+    // So, if we're logged in we use the API and in guest mode we rely on
+    // the JSON.
+    let info = if client.is_logged_in() {
+        get_info_from_api(client, id, media_type)
+            .context("get serie info from API")?
+    } else {
+        get_info_from_web(client, id, media_type)
+            .context("get serie info from web")?
+    };
+
+    Serie::from_data(info, client.base_url())
+}
+
+/// Extract serie info from Piccoma API.
+fn get_info_from_api(
+    client: &Client,
+    id: SerieID,
+    media_type: MediaType,
+) -> Result<models::serie::Data> {
+    let selector = match media_type {
+        MediaType::Episode => 'E',
+        MediaType::Volume => 'V',
+    };
+    let url = client.url(&format!("api/haribo/api/web/v3/product/{id}/episodes?episode_type={selector}&product_id={id}"));
+
+    Ok(client
+        .get_json::<models::serie::ApiResponse>(&url)
+        .context("call serie endpoint")?
+        .data)
+}
+
+/// Extract serie info from Piccoma web page.
+fn get_info_from_web(
+    client: &Client,
+    id: SerieID,
+    media_type: MediaType,
+) -> Result<models::serie::Data> {
+    // Fetch the serie page.
+    let url = client.url(&product_path(id, media_type));
+    let html = client.get_html(&url).context("get series page")?;
+
+    let data = get_next_data::<models::serie::NextData>(&html)
+        .context("get serie __NEXT_DATA__")?;
+
+    Ok(data
+        .props
+        .page_props
+        .initial_state
+        .product_home
+        .product_home)
+}
+
+/// Returns the path of the serie page, listing the media of `media_type`.
+pub(super) fn product_path(id: SerieID, media_type: MediaType) -> String {
+    let selector = match media_type {
+        MediaType::Episode => "episode",
+        MediaType::Volume => "volume",
+    };
+
+    format!("product/{selector}/{id}")
+}
+
+/// Retrieves the pages info from the media viewer at `url`.
+pub(super) fn get_viewer(client: &Client, url: &Url) -> Result<Viewer> {
+    // Fetch the viewer page.
+    let html = client.get_html(url).context("get viewer page")?;
+
+    let data = get_next_data::<models::viewer::NextData>(&html)
+        .context("get episode __NEXT_DATA__")?
+        .props
+        .page_props
+        .initial_state
+        .viewer
+        .p_data;
+
+    let pages = data
+        .img
+        .into_iter()
+        .map(|img| {
+            let number = page_number(&img.path)?;
+            Ok(Page::new(Region::Fr, img.path, number))
+        })
+        .collect::<Result<Vec<_>>>()
+        .context("invalid page URL")?;
+
+    Ok(Viewer {
+        is_scrambled: data.is_scrambled,
+        pages,
+    })
+}
+
+/// Extracts and parses the JSON payload embedded in the page.
+fn get_next_data<T>(html: &kuchiki::NodeRef) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let payload = NEXT_DATA_SELECTOR
+        .filter(html.descendants().elements())
+        .next()
+        .ok_or_else(|| eyre!("look for __NEXT_DATA__"))?
+        .text_contents();
+
+    serde_json::from_str::<T>(&payload).context("parse __NEXT_DATA__")
+}
+
+/// Extracts the page number from the image URL.
+fn page_number(url: &Url) -> Result<u16> {
+    let captures = PAGE_NUMBER
+        .captures(url.path())
+        .ok_or_else(|| eyre!("page number not found"))?;
+
+    Ok(captures
+        .name("number")
+        .expect("capture group 'number'")
+        .as_str()
+        .parse()
+        .expect("valid number")) // must be valid thanks to regex.
+}
+
+/// Returns the scrambling base key, provided through the parameter `q`.
+// https://cdn.fr.piccoma.com/308/9957/eeQoB4cdy4szeVhesSEpa2Sf7J9yoJM5dWFB5Zc/i00016.jpg?credential=&expires=1656892800&signature=YnD0viyplpST8e25GxQDzoirnKI%3D&q=Q9IXT44J6FDRRZB3KFSBJ7
+pub(super) fn scrambling_key(url: &Url) -> Option<Vec<u8>> {
+    let q = Cow::from("q");
+
+    url.query_pairs().find_map(|(key, value)| {
+        (key == q).then(|| value.into_owned().into_bytes())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_number_from_url() {
+        let url = Url::parse("https://cdn.fr.piccoma.com/308/9957/eeQoB4cdy4szeVhesSEpa2Sf7J9yoJM5dWFB5Zc/i00016.jpg?expires=1656892800").expect("valid URL");
+
+        let res = page_number(&url).expect("page number");
+
+        assert_eq!(res, 16);
+    }
+
+    #[test]
+    fn login_success() {
+        assert_eq!(login_error(200, r#"{"redirect":"/fr"}"#), None);
+        assert_eq!(login_error(200, "<html></html>"), None);
+    }
+
+    #[test]
+    fn login_failure() {
+        let captcha = r#"{"code":"RECAPTCHA_REQUIRED"}"#;
+        let locked = r#"{"code":"ACCOUNT_LOCKED","message":"Compte bloqué"}"#;
+        let unknown = r#"{"code":"MAINTENANCE","message":"En maintenance"}"#;
+
+        assert_eq!(
+            login_error(400, captcha),
+            Some(LoginError::CaptchaRequired)
+        );
+        assert_eq!(login_error(403, locked), Some(LoginError::AccountLocked));
+        assert_eq!(login_error(401, ""), Some(LoginError::BadCredentials));
+        assert_eq!(
+            login_error(503, unknown),
+            Some(LoginError::Rejected("En maintenance".to_owned()))
+        );
+        assert_eq!(
+            login_error(500, ""),
+            Some(LoginError::Rejected("HTTP 500".to_owned()))
+        );
+    }
+}
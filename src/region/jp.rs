@@ -0,0 +1,409 @@
+//! Japanese website: server-side rendered pages.
+
+use super::Viewer;
+use crate::{
+    media::{AccessType, MediaID},
+    page::Page,
+    selectors::{
+        JP_AUTHOR_SELECTOR, JP_CAPTCHA_SELECTOR, JP_COVER_SELECTOR,
+        JP_CSRF_SELECTOR, JP_DESCRIPTION_SELECTOR, JP_GENRE_SELECTOR,
+        JP_LABEL_SELECTOR, JP_LOGIN_ERROR_SELECTOR, JP_MEDIA_LINK_SELECTOR,
+        JP_MEDIA_SELECTOR, JP_MEDIA_STATUS_SELECTOR, JP_MEDIA_TITLE_SELECTOR,
+        JP_TITLE_SELECTOR, SCRIPT_SELECTOR,
+    },
+    serie::Details,
+    Client, Kind, LoginError, Media, MediaType, Region, Serie, SerieID, Status,
+};
+use eyre::{eyre, Result, WrapErr};
+use kuchiki::traits::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use url::Url;
+
+/// Match the scrambling flag in the viewer `_pdata_` object.
+static PDATA_SCRAMBLED: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"["']isScrambled["']\s*:\s*(?P<value>true|false)"#)
+        .expect("invalid scrambling flag regex")
+});
+
+/// Match the image paths in the viewer `_pdata_` object.
+static PDATA_PATH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"["']path["']\s*:\s*["'](?P<path>[^"']+)["']"#)
+        .expect("invalid image path regex")
+});
+
+/// Logs into the website using the specified credential.
+pub(super) fn login(
+    client: &Client,
+    email: &str,
+    password: &str,
+) -> Result<()> {
+    let url = client.url("acc/email/signin");
+
+    // The login form is protected by a CSRF token.
+    let html = client.get_html(&url).context("get login page")?;
+    let token = JP_CSRF_SELECTOR
+        .filter(html.descendants().elements())
+        .next()
+        .and_then(|input| {
+            input
+                .attributes
+                .borrow()
+                .get("value")
+                .map(ToOwned::to_owned)
+        })
+        .ok_or_else(|| eyre!("look for CSRF token"))?;
+    let next_url = client.base_url().path().to_owned();
+
+    let response = client
+        .post_form(
+            &url,
+            &[
+                ("csrfmiddlewaretoken", &token),
+                ("next_url", &next_url),
+                ("email", email),
+                ("password", password),
+            ],
+        )
+        .context("login")?;
+    let status = response.status();
+    // On success, we're redirected away from the login form.
+    let is_signin_form = Url::parse(response.get_url())
+        .map_or(true, |final_url| final_url.path() == url.path());
+    let body = response.into_string().context("read login response")?;
+    let form = is_signin_form.then(|| kuchiki::parse_html().one(body));
+
+    match login_error(status, form.as_ref()) {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// Extracts the reason of the login failure, if any, from the response.
+///
+/// On failure, the login `form` is displayed again with an error message.
+/// Any other page is the one we're redirected to after a successful login.
+fn login_error(
+    status: u16,
+    form: Option<&kuchiki::NodeRef>,
+) -> Option<LoginError> {
+    if let Some(html) = form {
+        let message = JP_LOGIN_ERROR_SELECTOR
+            .filter(html.descendants().elements())
+            .map(|node| node.text_contents().trim().to_owned())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if message.contains("パスワードが正しくありません") {
+            return Some(LoginError::BadCredentials);
+        }
+        if message.contains("アカウントがロック") {
+            return Some(LoginError::AccountLocked);
+        }
+        if !message.is_empty() {
+            return Some(LoginError::Rejected(message));
+        }
+        if JP_CAPTCHA_SELECTOR
+            .filter(html.descendants().elements())
+            .next()
+            .is_some()
+        {
+            return Some(LoginError::CaptchaRequired);
+        }
+    }
+    if status == 401 {
+        return Some(LoginError::BadCredentials);
+    }
+    if status >= 400 {
+        return Some(LoginError::Rejected(format!("HTTP {status}")));
+    }
+
+    None
+}
+
+/// Retrieves the serie info and its media list.
+pub(super) fn get_serie(
+    client: &Client,
+    id: SerieID,
+    media_type: MediaType,
+) -> Result<Serie> {
+    let selector = match media_type {
+        MediaType::Episode => 'E',
+        MediaType::Volume => 'V',
+    };
+    let url =
+        client.url(&format!("{}/episodes?etype={selector}", product_path(id)));
+    let html = client.get_html(&url).context("get series page")?;
+
+    let title = JP_TITLE_SELECTOR
+        .filter(html.descendants().elements())
+        .next()
+        .ok_or_else(|| eyre!("look for serie title"))?
+        .text_contents();
+
+    // Media are listed in reading order, their number is their position.
+    let media = JP_MEDIA_SELECTOR
+        .filter(html.descendants().elements())
+        .zip(1..)
+        .map(|(entry, number)| {
+            parse_media(entry.as_node(), id, media_type, number)
+                .with_context(|| format!("parse {media_type} {number}"))
+        })
+        .collect::<Result<Vec<_>>>()
+        .context("extract media")?;
+
+    let details = parse_details(&html, &url);
+
+    Serie::with_media(id, title.trim().to_owned(), details, media)
+}
+
+/// Extracts the serie details from its page at `url`.
+fn parse_details(html: &kuchiki::NodeRef, url: &Url) -> Details {
+    let texts = |selectors: &kuchiki::Selectors| {
+        selectors
+            .filter(html.descendants().elements())
+            .map(|node| node.text_contents().trim().to_owned())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+    };
+    let labels = texts(&JP_LABEL_SELECTOR);
+
+    Details {
+        authors: texts(&JP_AUTHOR_SELECTOR),
+        description: texts(&JP_DESCRIPTION_SELECTOR).into_iter().next(),
+        genres: texts(&JP_GENRE_SELECTOR),
+        // Paths are protocol-relative.
+        cover: JP_COVER_SELECTOR
+            .filter(html.descendants().elements())
+            .find_map(|image| {
+                let attributes = image.attributes.borrow();
+                attributes.get("src").and_then(|src| url.join(src).ok())
+            }),
+        status: labels.iter().find_map(|label| match label.as_str() {
+            "完結" => Some(Status::Completed),
+            "連載中" => Some(Status::Ongoing),
+            _ => None,
+        }),
+        kind: labels.iter().find_map(|label| label.parse::<Kind>().ok()),
+    }
+}
+
+/// Returns the path of the serie page.
+pub(super) fn product_path(id: SerieID) -> String {
+    format!("product/{id}")
+}
+
+/// Extracts a media from its entry in the media list.
+fn parse_media(
+    entry: &kuchiki::NodeRef,
+    serie_id: SerieID,
+    media_type: MediaType,
+    number: u16,
+) -> Result<Media> {
+    let id = JP_MEDIA_LINK_SELECTOR
+        .filter(entry.descendants().elements())
+        .next()
+        .and_then(|link| {
+            link.attributes
+                .borrow()
+                .get("data-episode_id")
+                .map(ToOwned::to_owned)
+        })
+        .ok_or_else(|| eyre!("look for media ID"))?
+        .parse::<MediaID>()?;
+    let title = JP_MEDIA_TITLE_SELECTOR
+        .filter(entry.descendants().elements())
+        .next()
+        .map(|title| title.text_contents())
+        .unwrap_or_default();
+    let access = JP_MEDIA_STATUS_SELECTOR
+        .filter(entry.descendants().elements())
+        .find_map(|status| {
+            status
+                .attributes
+                .borrow()
+                .get("class")
+                .and_then(access_type)
+        })
+        // No status means you have to pay.
+        .unwrap_or(AccessType::Paywalled);
+
+    // Page count is only known once the viewer is opened.
+    Ok(Media::new(
+        media_type,
+        id,
+        serie_id,
+        number,
+        title.trim(),
+        access,
+        None,
+    ))
+}
+
+/// Extracts the access type from the status classes.
+fn access_type(classes: &str) -> Option<AccessType> {
+    classes.split_whitespace().find_map(|class| {
+        let (_, status) = class.split_once("_status_")?;
+        Some(match status {
+            "free" => AccessType::Free,
+            "waitfreeRead" => AccessType::TemporaryFree,
+            "waitfree" | "webwaitfree" => AccessType::WaitUntilFree,
+            "point" => AccessType::Paywalled,
+            "purchased" => AccessType::Paid,
+            _ => return None,
+        })
+    })
+}
+
+/// Retrieves the pages info from the media viewer at `url`.
+pub(super) fn get_viewer(client: &Client, url: &Url) -> Result<Viewer> {
+    // Fetch the viewer page.
+    let html = client.get_html(url).context("get viewer page")?;
+
+    // Pages info are in a JS object, not a JSON payload.
+    let pdata = SCRIPT_SELECTOR
+        .filter(html.descendants().elements())
+        .map(|script| script.text_contents())
+        .find(|script| script.contains("_pdata_"))
+        .ok_or_else(|| eyre!("look for episode _pdata_"))?;
+
+    parse_pdata(url, &pdata)
+}
+
+/// Parses the pages info from the `_pdata_` object.
+fn parse_pdata(url: &Url, pdata: &str) -> Result<Viewer> {
+    let is_scrambled = PDATA_SCRAMBLED
+        .captures(pdata)
+        .ok_or_else(|| eyre!("look for scrambling flag"))?
+        .name("value")
+        .expect("capture group 'value'")
+        .as_str()
+        == "true";
+
+    // Pages are listed in reading order, their number is their position.
+    let pages = PDATA_PATH
+        .captures_iter(pdata)
+        .zip(1..)
+        .map(|(captures, number)| {
+            let path = captures.name("path").expect("capture group 'path'");
+            // Paths are protocol-relative.
+            url.join(path.as_str())
+                .map(|url| Page::new(Region::Jp, url, number))
+                .with_context(|| format!("invalid page URL {}", path.as_str()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Viewer {
+        is_scrambled,
+        pages,
+    })
+}
+
+/// Returns the scrambling base key, provided through the parent directory.
+// https://pcm-cdn.piccoma.com/f/32/1337/CF6X5G5DDW4BOKTZX2NA/1.jpg?expires=1656892800
+pub(super) fn scrambling_key(url: &Url) -> Option<Vec<u8>> {
+    let mut segments = url.path_segments()?.rev();
+    segments.next()?; // Skip the filename.
+
+    segments
+        .next()
+        .filter(|checksum| !checksum.is_empty())
+        .map(|checksum| checksum.as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_from_directory() {
+        let url = Url::parse("https://pcm-cdn.piccoma.com/f/32/1337/CF6X5G5DDW4BOKTZX2NA/1.jpg?expires=1656892800").expect("valid URL");
+
+        let res = scrambling_key(&url).expect("key");
+
+        assert_eq!(&res, b"CF6X5G5DDW4BOKTZX2NA");
+    }
+
+    #[test]
+    fn pdata() {
+        let url = Url::parse("https://piccoma.com/web/viewer/32/1337")
+            .expect("valid URL");
+        let pdata = r"
+            var _pdata_ = {
+                'title'       : 'Foo',
+                'isScrambled' : true,
+                'img'         : [
+                    {'path':'//pcm-cdn.piccoma.com/f/32/1337/CF6X/1.jpg?expires=1'},
+                    {'path':'//pcm-cdn.piccoma.com/f/32/1337/CF6X/2.jpg?expires=1'},
+                ],
+            };
+        ";
+
+        let res = parse_pdata(&url, pdata).expect("pdata");
+
+        assert!(res.is_scrambled);
+        assert_eq!(res.pages.len(), 2);
+        assert_eq!(
+            res.pages[1].url().as_str(),
+            "https://pcm-cdn.piccoma.com/f/32/1337/CF6X/2.jpg?expires=1"
+        );
+    }
+
+    #[test]
+    fn login_failure() {
+        let form = |content: &str| {
+            kuchiki::parse_html().one(format!(
+                "<html><body><form>{content}</form></body></html>"
+            ))
+        };
+        let captcha =
+            form(r#"<div class="g-recaptcha" data-sitekey="foo"></div>"#);
+        let invalid = form(
+            r#"<ul class="errorlist"><li>メールアドレスまたはパスワードが正しくありません</li></ul>"#,
+        );
+        let locked = form(
+            r#"<p class="PCM-formError">アカウントがロックされています</p>"#,
+        );
+
+        assert_eq!(login_error(200, Some(&form(""))), None);
+        assert_eq!(
+            login_error(200, Some(&captcha)),
+            Some(LoginError::CaptchaRequired)
+        );
+        assert_eq!(
+            login_error(200, Some(&invalid)),
+            Some(LoginError::BadCredentials)
+        );
+        assert_eq!(
+            login_error(200, Some(&locked)),
+            Some(LoginError::AccountLocked)
+        );
+    }
+
+    #[test]
+    fn login_success() {
+        // Redirected to the home page: its content doesn't matter.
+        assert_eq!(login_error(200, None), None);
+
+        // Only the errors of the form matter, not the rest of the page.
+        let html = kuchiki::parse_html().one(
+            r#"<html><body>
+                <h2>ロックは淑女の嗜みでして</h2>
+                <p class="error">ブロックされたユーザー</p>
+                <form><input name="email"></form>
+            </body></html>"#,
+        );
+        assert_eq!(login_error(200, Some(&html)), None);
+    }
+
+    #[test]
+    fn status_classes() {
+        let free = "PCM-epList_status PCM-epList_status_free";
+        let wuf = "PCM-epList_status_webwaitfree";
+
+        assert_eq!(access_type(free), Some(AccessType::Free));
+        assert_eq!(access_type(wuf), Some(AccessType::WaitUntilFree));
+        assert_eq!(access_type("PCM-epList_title"), None);
+    }
+}
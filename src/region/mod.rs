@@ -0,0 +1,137 @@
+//! Regional versions of the Piccoma website.
+//!
+//! Each region has its own layout and API, but they all boil down to the same
+//! operations: login, list the media of a serie and retrieve the pages of a
+//! media.
+
+mod fr;
+mod jp;
+
+use crate::{page::Page, Client, MediaType, Serie, SerieID};
+use clap::ArgEnum;
+use eyre::Result;
+use std::fmt;
+use url::Url;
+
+/// A regional version of the website.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ArgEnum)]
+pub enum Region {
+    /// French website (piccoma.com/fr).
+    Fr,
+    /// Japanese website (piccoma.com/web).
+    Jp,
+}
+
+impl Region {
+    /// Returns the default root URL of the website.
+    pub fn base_url(self) -> Url {
+        Url::parse(match self {
+            Self::Fr => "https://piccoma.com/fr/",
+            Self::Jp => "https://piccoma.com/web/",
+        })
+        .expect("valid region URL")
+    }
+
+    /// Returns the language of the website, as a BCP 47 tag.
+    pub fn language(self) -> &'static str {
+        match self {
+            Self::Fr => "fr",
+            Self::Jp => "ja",
+        }
+    }
+
+    /// Returns the name of the cookie holding the user session.
+    pub(crate) fn session_cookie(self) -> &'static str {
+        match self {
+            Self::Fr => "access_token",
+            Self::Jp => "sessionid",
+        }
+    }
+
+    /// Returns the path of the account page, only reachable when logged in.
+    pub(crate) fn account_path(self) -> &'static str {
+        match self {
+            Self::Fr => "account",
+            Self::Jp => "acc/top",
+        }
+    }
+
+    /// Logs into the website using the specified credential.
+    pub(crate) fn login(
+        self,
+        client: &Client,
+        email: &str,
+        password: &str,
+    ) -> Result<()> {
+        match self {
+            Self::Fr => fr::login(client, email, password),
+            Self::Jp => jp::login(client, email, password),
+        }
+    }
+
+    /// Retrieves the serie info and its media list.
+    pub(crate) fn get_serie(
+        self,
+        client: &Client,
+        id: SerieID,
+        media_type: MediaType,
+    ) -> Result<Serie> {
+        match self {
+            Self::Fr => fr::get_serie(client, id, media_type),
+            Self::Jp => jp::get_serie(client, id, media_type),
+        }
+    }
+
+    /// Returns the path of the serie page, relative to the root URL.
+    pub(crate) fn product_path(
+        self,
+        id: SerieID,
+        media_type: MediaType,
+    ) -> String {
+        match self {
+            Self::Fr => fr::product_path(id, media_type),
+            Self::Jp => jp::product_path(id),
+        }
+    }
+
+    /// Retrieves the pages info from the media viewer at `url`.
+    pub(crate) fn get_viewer(
+        self,
+        client: &Client,
+        url: &Url,
+    ) -> Result<Viewer> {
+        match self {
+            Self::Fr => fr::get_viewer(client, url),
+            Self::Jp => jp::get_viewer(client, url),
+        }
+    }
+
+    /// Returns the base key used to compute the scrambling seed of `url`.
+    pub(crate) fn scrambling_key(self, url: &Url) -> Option<Vec<u8>> {
+        match self {
+            Self::Fr => fr::scrambling_key(url),
+            Self::Jp => jp::scrambling_key(url),
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Fr => "fr",
+                Self::Jp => "jp",
+            }
+        )
+    }
+}
+
+/// Pages info extracted from a media viewer.
+pub(crate) struct Viewer {
+    /// Are those page scrambled?
+    pub(crate) is_scrambled: bool,
+    /// Media pages.
+    pub(crate) pages: Vec<Page>,
+}
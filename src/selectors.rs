@@ -6,3 +6,100 @@ pub(crate) static NEXT_DATA_SELECTOR: Lazy<kuchiki::Selectors> =
         kuchiki::Selectors::compile("script#__NEXT_DATA__")
             .expect("invalid serie JSON payload selector")
     });
+
+/// Select the serie title on the japanese website.
+pub(crate) static JP_TITLE_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile("h1.PCM-productTitle")
+            .expect("invalid serie title selector")
+    });
+
+/// Select the serie authors on the japanese website.
+pub(crate) static JP_AUTHOR_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(".PCM-productAuthor li")
+            .expect("invalid serie author selector")
+    });
+
+/// Select the serie synopsis on the japanese website.
+pub(crate) static JP_DESCRIPTION_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(".PCM-productDesc")
+            .expect("invalid serie description selector")
+    });
+
+/// Select the serie genres on the japanese website.
+pub(crate) static JP_GENRE_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(".PCM-productGenre li")
+            .expect("invalid serie genre selector")
+    });
+
+/// Select the serie cover on the japanese website.
+pub(crate) static JP_COVER_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(".PCM-productThum img")
+            .expect("invalid serie cover selector")
+    });
+
+/// Select the serie labels (kind, completion, ...) on the japanese website.
+pub(crate) static JP_LABEL_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(".PCM-productLabel li")
+            .expect("invalid serie label selector")
+    });
+
+/// Select the media entries (episodes or volumes) on the japanese website.
+pub(crate) static JP_MEDIA_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile("#js_episodeList > li, .PCM-volList > li")
+            .expect("invalid media list selector")
+    });
+
+/// Select the link holding the media ID on the japanese website.
+pub(crate) static JP_MEDIA_LINK_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile("a[data-episode_id]")
+            .expect("invalid media link selector")
+    });
+
+/// Select the media title on the japanese website.
+pub(crate) static JP_MEDIA_TITLE_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile("h2").expect("invalid media title selector")
+    });
+
+/// Select the media access status on the japanese website.
+pub(crate) static JP_MEDIA_STATUS_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(r#"[class*="_status_"]"#)
+            .expect("invalid media status selector")
+    });
+
+/// Select the CSRF token of the japanese login form.
+pub(crate) static JP_CSRF_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(r#"input[name="csrfmiddlewaretoken"]"#)
+            .expect("invalid CSRF token selector")
+    });
+
+/// Select the error messages of the japanese login form.
+pub(crate) static JP_LOGIN_ERROR_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile(
+            r#"form .errorlist, form [class*="error"], form [class*="Error"]"#,
+        )
+        .expect("invalid login error selector")
+    });
+
+/// Select the CAPTCHA widget of the japanese login form.
+pub(crate) static JP_CAPTCHA_SELECTOR: Lazy<kuchiki::Selectors> =
+    Lazy::new(|| {
+        kuchiki::Selectors::compile("form .g-recaptcha")
+            .expect("invalid CAPTCHA selector")
+    });
+
+/// Select the inline scripts.
+pub(crate) static SCRIPT_SELECTOR: Lazy<kuchiki::Selectors> = Lazy::new(|| {
+    kuchiki::Selectors::compile("script").expect("invalid script selector")
+});
@@ -1,14 +1,19 @@
-use crate::{models, Client, Media, MediaType, NEXT_DATA_SELECTOR};
-use eyre::{ensure, eyre, Result, WrapErr};
-use kuchiki::traits::*;
+use crate::{models, Client, Media, MediaType};
+use eyre::{ensure, Result, WrapErr};
+use serde::Serialize;
 use std::{fmt, str::FromStr};
 use url::Url;
 
 /// A media serie.
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Serie {
+    /// Serie ID.
+    id: SerieID,
     /// Serie title.
     title: String,
+    /// Descriptive information.
+    #[serde(flatten)]
+    details: Details,
     /// Media list.
     media: Vec<Media>,
 }
@@ -20,25 +25,72 @@ impl Serie {
         id: SerieID,
         media_type: MediaType,
     ) -> Result<Self> {
-        // We have two way of extracting the list of media:
-        // - the API
-        // - the embedded JSON payload
-        //
-        // API can only be used if you are logged in.
-        // Embedded JSON payload only contains unread media when you're
-        // logged in, otherwise it's complete.
-        //
-        // So, if we're logged in we use the API and in guest mode we rely on
-        // the JSON.
-        let info = if client.is_logged_in() {
-            get_info_from_api(client, id, media_type)
-                .context("get serie info from API")?
-        } else {
-            get_info_from_web(client, id, media_type)
-                .context("get serie info from web")?
+        client.region().get_serie(client, id, media_type)
+    }
+
+    /// Initializes a serie from its title, details and media list.
+    pub(crate) fn with_media(
+        id: SerieID,
+        title: String,
+        details: Details,
+        media: Vec<Media>,
+    ) -> Result<Self> {
+        ensure!(!title.is_empty(), "empty serie title");
+
+        Ok(Self {
+            id,
+            title,
+            details,
+            media,
+        })
+    }
+
+    /// Initializes a serie from the website data, rooted at `base_url`.
+    pub(crate) fn from_data(
+        value: models::serie::Data,
+        base_url: &Url,
+    ) -> Result<Self> {
+        let media = value
+            .media_list
+            .into_iter()
+            .map(Media::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .context("extract media")?;
+        let product = value.product;
+        let details = Details {
+            authors: product
+                .author_list
+                .into_iter()
+                .map(|author| author.name)
+                .collect(),
+            description: product
+                .description
+                .filter(|description| !description.is_empty()),
+            genres: product
+                .genre_list
+                .into_iter()
+                .map(|genre| genre.name)
+                .collect(),
+            // Paths may be relative to the website.
+            cover: product
+                .thumbnail_path
+                .and_then(|path| base_url.join(&path).ok()),
+            status: product.is_completed.map(|is_completed| {
+                if is_completed {
+                    Status::Completed
+                } else {
+                    Status::Ongoing
+                }
+            }),
+            kind: product.product_type.and_then(|kind| kind.parse().ok()),
         };
 
-        info.try_into()
+        Self::with_media(product.id.into(), product.title, details, media)
+    }
+
+    /// Returns the serie ID.
+    pub fn id(&self) -> SerieID {
+        self.id
     }
 
     /// Returns the series title.
@@ -46,6 +98,36 @@ impl Serie {
         &self.title
     }
 
+    /// Returns the authors (writer, artist, ...).
+    pub fn authors(&self) -> &[String] {
+        &self.details.authors
+    }
+
+    /// Returns the synopsis, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.details.description.as_deref()
+    }
+
+    /// Returns the genres.
+    pub fn genres(&self) -> &[String] {
+        &self.details.genres
+    }
+
+    /// Returns the URL of the cover image, if any.
+    pub fn cover_url(&self) -> Option<&Url> {
+        self.details.cover.as_ref()
+    }
+
+    /// Returns the completion status, if known.
+    pub fn status(&self) -> Option<Status> {
+        self.details.status
+    }
+
+    /// Returns the kind of work, if known.
+    pub fn kind(&self) -> Option<Kind> {
+        self.details.kind
+    }
+
     /// Returns the number of media.
     pub fn media_count(&self) -> usize {
         self.media.len()
@@ -57,71 +139,79 @@ impl Serie {
     }
 }
 
-/// Extract serie info from Piccoma API.
-fn get_info_from_api(
-    client: &Client,
-    id: SerieID,
-    media_type: MediaType,
-) -> Result<models::serie::Data> {
-    let selector = match media_type {
-        MediaType::Episode => 'E',
-        MediaType::Volume => 'V',
-    };
-    let url = Url::parse(&format!("https://piccoma.com/fr/api/haribo/api/web/v3/product/{id}/episodes?episode_type={selector}&product_id={id}")).expect("valid serie API URL");
-
-    Ok(client
-        .get_json::<models::serie::ApiResponse>(&url)
-        .context("call serie endpoint")?
-        .data)
+// -----------------------------------------------------------------------------
+
+/// Descriptive information about a serie, as far as the website provides it.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Details {
+    /// Authors (writer, artist, ...).
+    pub(crate) authors: Vec<String>,
+    /// Synopsis.
+    pub(crate) description: Option<String>,
+    /// Genres.
+    pub(crate) genres: Vec<String>,
+    /// Cover image URL.
+    pub(crate) cover: Option<Url>,
+    /// Completion status.
+    pub(crate) status: Option<Status>,
+    /// Kind of work.
+    pub(crate) kind: Option<Kind>,
 }
 
-/// Extract serie info from Piccoma web page.
-fn get_info_from_web(
-    client: &Client,
-    id: SerieID,
-    media_type: MediaType,
-) -> Result<models::serie::Data> {
-    let selector = match media_type {
-        MediaType::Episode => "episode",
-        MediaType::Volume => "volume",
-    };
-    // Fetch the serie page.
-    let url =
-        Url::parse(&format!("https://piccoma.com/fr/product/{selector}/{id}"))
-            .expect("valid serie web URL");
-    let html = client.get_html(&url).context("get series page")?;
-
-    // Extract and parse the JSON payload.
-    let payload = NEXT_DATA_SELECTOR
-        .filter(html.descendants().elements())
-        .next()
-        .ok_or_else(|| eyre!("look for serie __NEXT_DATA__"))?
-        .text_contents();
-    let data = serde_json::from_str::<models::serie::NextData>(&payload)
-        .context("parse serie __NEXT_DATA__")?;
-
-    Ok(data
-        .props
-        .page_props
-        .initial_state
-        .product_home
-        .product_home)
+/// Completion status of a serie.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// New media are still being published.
+    Ongoing,
+    /// Every media has been published.
+    Completed,
 }
 
-impl TryFrom<models::serie::Data> for Serie {
-    type Error = eyre::Report;
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Ongoing => "ongoing",
+                Self::Completed => "completed",
+            }
+        )
+    }
+}
 
-    fn try_from(value: models::serie::Data) -> Result<Self, Self::Error> {
-        ensure!(!value.product.title.is_empty(), "empty serie title");
+/// Kind of work.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// Paginated comic, read page by page.
+    Manga,
+    /// Vertical-scrolling comic, made for smartphones.
+    Smartoon,
+}
 
-        Ok(Self {
-            title: value.product.title,
-            media: value
-                .media_list
-                .into_iter()
-                .map(Media::try_from)
-                .collect::<Result<Vec<_>, _>>()
-                .context("extract media")?,
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Manga => "manga",
+                Self::Smartoon => "smartoon",
+            }
+        )
+    }
+}
+
+impl FromStr for Kind {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value.to_lowercase().as_str() {
+            "manga" | "comic" | "マンガ" => Self::Manga,
+            "smartoon" | "webtoon" | "スマトゥーン" => Self::Smartoon,
+            _ => eyre::bail!("{value} is not a valid kind of work"),
         })
     }
 }
@@ -129,7 +219,9 @@ impl TryFrom<models::serie::Data> for Serie {
 // -----------------------------------------------------------------------------
 
 /// Serie ID on Piccoma.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize,
+)]
 pub struct SerieID(u32);
 
 impl fmt::Display for SerieID {
@@ -144,6 +236,12 @@ impl From<u32> for SerieID {
     }
 }
 
+impl From<SerieID> for u32 {
+    fn from(value: SerieID) -> Self {
+        value.0
+    }
+}
+
 impl FromStr for SerieID {
     type Err = eyre::Report;
 
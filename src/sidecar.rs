@@ -0,0 +1,139 @@
+//! Serie-level files, stored alongside the media: cover and metadata.
+
+use crate::{fs, Client, Serie, Status};
+use eyre::{Result, WrapErr};
+use image::ImageOutputFormat;
+use serde::Serialize;
+use std::{fs as stdfs, io::Cursor, path::Path};
+use url::Url;
+
+/// Name of the cover image.
+pub const COVER_FILENAME: &str = "cover.jpg";
+/// Name of the serie metadata file.
+pub const SERIES_FILENAME: &str = "series.json";
+
+/// Writes the serie cover and metadata in `directory`.
+///
+/// The metadata file is only rewritten when its content changes, and the
+/// cover is downloaded again when its URL changes.
+pub fn update(client: &Client, directory: &Path, serie: &Serie) -> Result<()> {
+    let path = directory.join(SERIES_FILENAME);
+    let previous = stdfs::read(&path).ok();
+
+    // The cover is saved first: the metadata holds its URL, once written the
+    // cover won't be downloaded again.
+    if let Some(url) = serie.cover_url() {
+        let cover_path = directory.join(COVER_FILENAME);
+        let previous_url = previous
+            .as_deref()
+            .and_then(|bytes| {
+                serde_json::from_slice::<serde_json::Value>(bytes).ok()
+            })
+            .and_then(|json| {
+                json["metadata"]["ComicImage"]
+                    .as_str()
+                    .map(ToOwned::to_owned)
+            });
+
+        if !cover_path.is_file()
+            || previous_url.as_deref() != Some(url.as_str())
+        {
+            download_cover(client, url, &cover_path)
+                .context("download cover")?;
+        }
+    }
+
+    let content = serde_json::to_vec_pretty(&SeriesFile::new(serie))
+        .context("serialize serie metadata")?;
+    if previous.as_deref() != Some(content.as_slice()) {
+        fs::atomic_write(&path, &content)
+            .with_context(|| format!("save {SERIES_FILENAME}"))?;
+    }
+
+    Ok(())
+}
+
+/// Downloads the cover at `url`, as JPEG.
+fn download_cover(client: &Client, url: &Url, path: &Path) -> Result<()> {
+    let mut bytes = Vec::new();
+    client
+        .get_image(url, &mut bytes)
+        .with_context(|| format!("download image from {url}"))?;
+
+    // Convert the image if it isn't already a JPEG.
+    if !bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        let image = image::load_from_memory(&bytes)
+            .with_context(|| format!("decode image from {url}"))?;
+        let mut jpeg = Cursor::new(Vec::new());
+        image
+            .write_to(&mut jpeg, ImageOutputFormat::Jpeg(90))
+            .context("encode cover")?;
+        bytes = jpeg.into_inner();
+    }
+
+    fs::atomic_write(path, &bytes)
+        .with_context(|| format!("save {COVER_FILENAME}"))
+}
+
+/// Content of `series.json`, in the Mylar format (also read by Komga).
+///
+/// See <https://github.com/mylar3/mylar3/wiki/series.json-schema-(version-1.0.2)>
+#[derive(Serialize)]
+struct SeriesFile<'a> {
+    version: &'static str,
+    metadata: SeriesMetadata<'a>,
+}
+
+#[derive(Serialize)]
+struct SeriesMetadata<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    publisher: &'static str,
+    imprint: Option<&'a str>,
+    name: &'a str,
+    comicid: u32,
+    year: Option<u16>,
+    description_text: Option<&'a str>,
+    description_formatted: Option<&'a str>,
+    volume: Option<u16>,
+    booktype: &'static str,
+    age_rating: Option<&'a str>,
+    collects: Option<&'a str>,
+    #[serde(rename = "ComicImage")]
+    comic_image: Option<&'a str>,
+    total_issues: usize,
+    publication_run: Option<&'a str>,
+    status: Option<&'static str>,
+    /// Not part of the Mylar format, ignored by its readers.
+    authors: &'a [String],
+}
+
+impl<'a> SeriesFile<'a> {
+    fn new(serie: &'a Serie) -> Self {
+        Self {
+            version: "1.0.2",
+            metadata: SeriesMetadata {
+                kind: "comicSeries",
+                publisher: "Piccoma",
+                imprint: None,
+                name: serie.title(),
+                comicid: serie.id().into(),
+                year: None,
+                description_text: serie.description(),
+                description_formatted: None,
+                volume: None,
+                booktype: "Print",
+                age_rating: None,
+                collects: None,
+                comic_image: serie.cover_url().map(Url::as_str),
+                total_issues: serie.media_count(),
+                publication_run: None,
+                status: serie.status().map(|status| match status {
+                    Status::Ongoing => "Continuing",
+                    Status::Completed => "Ended",
+                }),
+                authors: serie.authors(),
+            },
+        }
+    }
+}
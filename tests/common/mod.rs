@@ -0,0 +1,416 @@
+//! Mock of the Piccoma website, serving recorded payloads.
+
+// Not every test suite uses every helper.
+#![allow(dead_code)]
+
+use braque::BlockSize;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use piconbiere::{Client, Region};
+use std::{
+    fs,
+    io::Cursor,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+use url::{Position, Url};
+
+/// ID of the mocked serie.
+pub const SERIE_ID: u32 = 42;
+/// Title of the mocked serie.
+pub const SERIE_TITLE: &str = "Mock Serie";
+/// Title of the mocked serie, on the japanese website.
+pub const JP_SERIE_TITLE: &str = "モックシリーズ";
+/// Credentials accepted by the mock.
+pub const EMAIL: &str = "jdoe@example.com";
+pub const PASSWORD: &str = "hunter2";
+/// Accounts for which the login fails.
+pub const LOCKED_EMAIL: &str = "locked@example.com";
+pub const NO_SESSION_EMAIL: &str = "nosession@example.com";
+/// Access token set upon successful login.
+pub const ACCESS_TOKEN: &str = "mock-access-token";
+
+/// Episodes of the mocked serie: ID, number of pages and scrambling.
+const EPISODES: [(u32, u16, bool); 3] =
+    [(1001, 3, true), (1002, 2, true), (1003, 2, false)];
+
+/// Scrambling parameters, shared by every page.
+///
+/// The key is given as parameter on the french website, and as directory on
+/// the japanese one.
+const EXPIRES: &str = "4102444809";
+const KEY: &str = "IH7SKRE4KR9FHBRB81GVIX";
+const SEED: &[u8] = b"KR9FHBRB81GVIXIH7SKRE4";
+
+/// A local HTTP server mimicking the Piccoma website.
+pub struct MockServer {
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+    root_url: Url,
+    /// Signature of the valid image URLs, changed when they expire.
+    signature: Arc<AtomicU32>,
+}
+
+impl MockServer {
+    /// Starts the server on a random port.
+    pub fn start() -> Self {
+        let server =
+            Arc::new(Server::http("127.0.0.1:0").expect("start mock server"));
+        let addr = server.server_addr().to_ip().expect("IP address");
+        let root_url =
+            Url::parse(&format!("http://{addr}/")).expect("root URL");
+
+        let signature = Arc::new(AtomicU32::new(0));
+
+        let handle = {
+            let server = Arc::clone(&server);
+            let root_url = root_url.clone();
+            let signature = Arc::clone(&signature);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(
+                        request,
+                        &root_url,
+                        signature.load(Ordering::SeqCst),
+                    );
+                }
+            })
+        };
+
+        Self {
+            server,
+            handle: Some(handle),
+            root_url,
+            signature,
+        }
+    }
+
+    /// Expires the image URLs given so far: they're now denied access.
+    pub fn expire_urls(&self) {
+        self.signature.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns a client targeting the mock, without delay nor retry.
+    pub fn client(&self) -> Client {
+        self.region_client(Region::Fr)
+    }
+
+    /// Returns a client targeting the `region` website of the mock.
+    pub fn region_client(&self, region: Region) -> Client {
+        let base_url = self
+            .root_url
+            .join(match region {
+                Region::Fr => "fr/",
+                Region::Jp => "web/",
+            })
+            .expect("base URL");
+
+        Client::new(region, base_url, 0).with_delay(Duration::ZERO)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("stop mock server");
+        }
+    }
+}
+
+/// Returns a unique path in the temporary directory.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("piconbiere-{}-{name}", std::process::id()))
+}
+
+/// Returns the expected content of the page `number` of `episode_id`.
+pub fn page(episode_id: u32, number: u16) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(120, 170, |x, y| {
+        #[allow(clippy::cast_possible_truncation)]
+        Rgb([
+            (x * 2) as u8,
+            (y + u32::from(number) * 10) as u8,
+            (episode_id % 256) as u8,
+        ])
+    }))
+}
+
+/// Dispatches the request to the right handler.
+///
+/// Only image URLs carrying `signature` are valid.
+fn handle(mut request: Request, root_url: &Url, signature: u32) {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut body)
+        .expect("read request body");
+    let url = root_url.join(request.url()).expect("request URL");
+    let segments = url
+        .path_segments()
+        .map(Iterator::collect::<Vec<_>>)
+        .unwrap_or_default();
+
+    let response = match (request.method(), segments.as_slice()) {
+        (&Method::Get, ["fr", "product", "episode", id])
+            if *id == SERIE_ID.to_string() =>
+        {
+            html(
+                fixture("serie.html")
+                    .replace("{{root_url}}", root_url.as_str()),
+            )
+        },
+        (
+            &Method::Get,
+            ["fr", "api", "haribo", "api", "web", "v3", "product", id, "episodes"],
+        ) if *id == SERIE_ID.to_string() => {
+            if has_access_token(&request) {
+                json(
+                    fixture("episodes.json")
+                        .replace("{{root_url}}", root_url.as_str()),
+                )
+            } else {
+                Response::from_string("").with_status_code(401)
+            }
+        },
+        (&Method::Post, ["fr", "api", "auth", "signin"]) => signin(&body),
+        (&Method::Get, ["fr", "account"]) => {
+            if has_access_token(&request) {
+                html("<html></html>".to_owned())
+            } else {
+                redirect(&root_url.join("fr/signin").expect("signin URL"))
+            }
+        },
+        (&Method::Get, ["fr", "signin"]) => html("<html></html>".to_owned()),
+        (&Method::Get, ["fr", "viewer", serie_id, episode_id])
+            if *serie_id == SERIE_ID.to_string() =>
+        {
+            episode_id
+                .parse()
+                .ok()
+                .and_then(|id| viewer(root_url, id, signature))
+                .unwrap_or_else(not_found)
+        },
+        (&Method::Get, ["web", "product", id, "episodes"])
+            if *id == SERIE_ID.to_string() =>
+        {
+            let host = &root_url[Position::BeforeHost..Position::AfterPort];
+            html(fixture("jp_serie.html").replace("{{host}}", host))
+        },
+        (&Method::Get, ["web", "viewer", serie_id, episode_id])
+            if *serie_id == SERIE_ID.to_string() =>
+        {
+            episode_id
+                .parse()
+                .ok()
+                .and_then(|id| jp_viewer(root_url, id, signature))
+                .unwrap_or_else(not_found)
+        },
+        (&Method::Get, ["covers", filename])
+            if *filename == format!("{SERIE_ID}.jpg") =>
+        {
+            cover()
+        },
+        (&Method::Get, ["images", episode_id, filename])
+        | (&Method::Get, ["images", episode_id, KEY, filename]) => {
+            if has_signature(&url, signature) {
+                image(episode_id, filename).unwrap_or_else(not_found)
+            } else {
+                Response::from_string("").with_status_code(403)
+            }
+        },
+        _ => not_found(),
+    };
+
+    request.respond(response).expect("send response");
+}
+
+/// Logs the user in, if the credentials are valid.
+fn signin(body: &str) -> Response<Cursor<Vec<u8>>> {
+    let credentials: serde_json::Value =
+        serde_json::from_str(body).expect("JSON body");
+    if credentials["email"] == EMAIL && credentials["password"] == PASSWORD {
+        json(r#"{"redirect":"/fr"}"#.to_owned()).with_header(header(
+            "Set-Cookie",
+            &format!("access_token={ACCESS_TOKEN}; Path=/"),
+        ))
+    } else if credentials["email"] == LOCKED_EMAIL {
+        json(
+            r#"{"code":"ACCOUNT_LOCKED","message":"Compte bloqué"}"#.to_owned(),
+        )
+        .with_status_code(403)
+    } else if credentials["email"] == NO_SESSION_EMAIL {
+        json(r#"{"redirect":"/fr"}"#.to_owned())
+    } else {
+        json(
+            r#"{"code":"INVALID_CREDENTIALS","message":"Identifiants invalides"}"#
+                .to_owned(),
+        )
+        .with_status_code(401)
+    }
+}
+
+/// Renders the viewer page of an episode.
+fn viewer(
+    root_url: &Url,
+    episode_id: u32,
+    signature: u32,
+) -> Option<Response<Cursor<Vec<u8>>>> {
+    let (_, page_count, is_scrambled) = EPISODES
+        .into_iter()
+        .find(|episode| episode.0 == episode_id)?;
+    let images = (1..=page_count)
+        .map(|number| {
+            let url = root_url
+                .join(&format!(
+                    "/images/{episode_id}/i{number:05}.png?expires={EXPIRES}&q={KEY}&sig={signature}"
+                ))
+                .expect("image URL");
+            format!(r#"{{"path":"{url}"}}"#)
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    Some(html(
+        fixture("viewer.html")
+            .replace("{{is_scrambled}}", &is_scrambled.to_string())
+            .replace("{{images}}", &images)
+            .replace("{{episode_id}}", &episode_id.to_string()),
+    ))
+}
+
+/// Renders the viewer page of an episode, on the japanese website.
+fn jp_viewer(
+    root_url: &Url,
+    episode_id: u32,
+    signature: u32,
+) -> Option<Response<Cursor<Vec<u8>>>> {
+    let host = &root_url[Position::BeforeHost..Position::AfterPort];
+    let (_, page_count, is_scrambled) = EPISODES
+        .into_iter()
+        .find(|episode| episode.0 == episode_id)?;
+    // Image paths are protocol-relative, with the key as directory.
+    let images = (1..=page_count)
+        .map(|number| {
+            format!(
+                "{{'path':'//{host}/images/{episode_id}/{KEY}/i{number:05}.png?expires={EXPIRES}&sig={signature}'}},"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(html(
+        fixture("jp_viewer.html")
+            .replace("{{is_scrambled}}", &is_scrambled.to_string())
+            .replace("{{images}}", &images)
+            .replace("{{episode_id}}", &episode_id.to_string()),
+    ))
+}
+
+/// Serves a page image, scrambled if the episode is.
+fn image(
+    episode_id: &str,
+    filename: &str,
+) -> Option<Response<Cursor<Vec<u8>>>> {
+    let episode_id = episode_id.parse().ok()?;
+    let number = filename
+        .strip_prefix('i')?
+        .strip_suffix(".png")?
+        .parse()
+        .ok()?;
+    let (_, page_count, is_scrambled) = EPISODES
+        .into_iter()
+        .find(|episode| episode.0 == episode_id)?;
+    if number == 0 || number > page_count {
+        return None;
+    }
+
+    let mut image = page(episode_id, number);
+    if is_scrambled {
+        let block_size = BlockSize::try_from(50).expect("valid block size");
+        image = braque::unscramble(&image, block_size, SEED);
+    }
+
+    Some(
+        Response::from_data(png(&image))
+            .with_header(header("Content-Type", "image/png")),
+    )
+}
+
+/// Encodes an image as PNG, as served by the CDN.
+pub fn png(image: &DynamicImage) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, ImageOutputFormat::Png)
+        .expect("encode PNG");
+
+    bytes.into_inner()
+}
+
+/// Tests if the image URL carries the current signature.
+fn has_signature(url: &Url, signature: u32) -> bool {
+    url.query_pairs()
+        .any(|(key, value)| key == "sig" && value == signature.to_string())
+}
+
+/// Serves the serie cover.
+fn cover() -> Response<Cursor<Vec<u8>>> {
+    let mut bytes = Cursor::new(Vec::new());
+    page(0, 0)
+        .write_to(&mut bytes, ImageOutputFormat::Jpeg(90))
+        .expect("encode JPEG");
+
+    Response::from_data(bytes.into_inner())
+        .with_header(header("Content-Type", "image/jpeg"))
+}
+
+/// Tests if the request carries the access token set upon login.
+fn has_access_token(request: &Request) -> bool {
+    request.headers().iter().any(|header| {
+        header.field.equiv("Cookie")
+            && header
+                .value
+                .as_str()
+                .split("; ")
+                .any(|cookie| cookie == format!("access_token={ACCESS_TOKEN}"))
+    })
+}
+
+/// Reads a recorded payload.
+fn fixture(name: &str) -> String {
+    let path = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect::<PathBuf>();
+
+    fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("read {}: {err}", path.display()))
+}
+
+fn html(body: String) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_header(header("Content-Type", "text/html; charset=utf-8"))
+}
+
+fn json(body: String) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn redirect(url: &Url) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string("")
+        .with_status_code(302)
+        .with_header(header("Location", url.as_str()))
+}
+
+fn not_found() -> Response<Cursor<Vec<u8>>> {
+    Response::from_string("").with_status_code(404)
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("valid header")
+}
@@ -0,0 +1,683 @@
+mod common;
+
+use common::{
+    MockServer, EMAIL, JP_SERIE_TITLE, PASSWORD, SERIE_ID, SERIE_TITLE,
+};
+use image::DynamicImage;
+use piconbiere::{
+    cbz, config::Config, directory, epub, pdf, pipeline, sidecar, Client,
+    Direction, EncodedPage, Encoding, Kind, Media, MediaType, Metadata,
+    OutputFormat, PageContent, PageEncoder, Region, Serie, Status,
+};
+use std::{
+    io::{Cursor, Read},
+    path::Path,
+};
+use url::Url;
+use zip::{CompressionMethod, ZipArchive};
+
+/// Returns the media `number` of the serie.
+fn media(serie: &Serie, number: u16) -> &Media {
+    serie
+        .media()
+        .find(|media| media.number() == number)
+        .expect("media in serie")
+}
+
+/// Downloads every page of the media.
+fn fetch_pages(client: &Client, media: &Media) -> Vec<DynamicImage> {
+    media
+        .fetch_pages(client.clone())
+        .expect("fetch pages")
+        .map(|page| page.and_then(PageContent::into_image))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("download pages")
+}
+
+/// Downloads and encodes every page of the media, in background.
+fn encode_pages(
+    client: Client,
+    media: &Media,
+    encoder: PageEncoder,
+) -> pipeline::EncodedPages {
+    let pages = media.fetch_pages(client).expect("fetch pages");
+
+    pipeline::encode(pages, move |page| encoder.encode(page))
+}
+
+/// Saves the pages as a CBZ archive at `path`, streamed to disk.
+fn save_cbz(
+    path: &Path,
+    metadata: &Metadata<'_>,
+    pages: impl Iterator<Item = eyre::Result<EncodedPage>>,
+) -> eyre::Result<()> {
+    let file = piconbiere::fs::AtomicFile::create(path)?;
+
+    cbz::write(file, metadata, pages)?.commit()
+}
+
+#[test]
+fn guest_serie() {
+    let server = MockServer::start();
+    let client = server.client();
+
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    assert!(!client.is_logged_in());
+    assert_eq!(serie.title(), SERIE_TITLE);
+    assert_eq!(serie.media_count(), 3);
+    assert_eq!(media(&serie, 1).title(), "001 - Le commencement");
+    assert_eq!(media(&serie, 3).title(), "Episode 003");
+    assert!(media(&serie, 1).is_available());
+    assert!(!media(&serie, 2).is_available());
+    assert!(!media(&serie, 3).is_available());
+}
+
+#[test]
+fn serie_json() {
+    let server = MockServer::start();
+    let client = server.client();
+
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let json = serde_json::to_value(&serie).expect("serialize serie");
+
+    assert_eq!(json["id"], SERIE_ID);
+    assert_eq!(json["title"], SERIE_TITLE);
+    assert_eq!(json["media"].as_array().map(Vec::len), Some(3));
+    assert_eq!(json["media"][0]["title"], "001 - Le commencement");
+    assert_eq!(json["media"][0]["type"], "episode");
+    assert_eq!(json["media"][0]["access"], "free");
+    assert_eq!(json["media"][0]["page_count"], 3);
+    assert_eq!(json["media"][1]["access"], "wait_until_free");
+}
+
+#[test]
+fn logged_in_serie() {
+    let server = MockServer::start();
+    let client = server.client();
+
+    client.login(EMAIL, PASSWORD).expect("login");
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    assert!(client.is_logged_in());
+    assert_eq!(serie.title(), SERIE_TITLE);
+    assert_eq!(serie.media_count(), 3);
+    assert!(media(&serie, 1).is_available());
+    assert!(!media(&serie, 2).is_available());
+    assert!(media(&serie, 3).is_available());
+    assert_eq!(serie.authors(), ["Jane Doe", "John Smith"]);
+    assert_eq!(serie.genres(), ["Action", "Fantasy"]);
+    assert_eq!(
+        serie.description(),
+        Some("Une histoire <de> test & de mock.")
+    );
+    assert_eq!(serie.status(), Some(Status::Ongoing));
+    assert_eq!(serie.kind(), Some(Kind::Smartoon));
+    // Relative to the website, not to the production one.
+    assert_eq!(serie.cover_url(), Some(&client.url("/covers/42.jpg")));
+}
+
+#[test]
+fn scrambled_pages() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    let pages = fetch_pages(&client, media(&serie, 1));
+
+    assert_eq!(pages.len(), 3);
+    for (number, page) in (1..).zip(pages) {
+        assert_eq!(page, common::page(1001, number), "page {number}");
+    }
+}
+
+#[test]
+fn concurrent_pages() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    let pages = media(&serie, 1)
+        .fetch_pages(client)
+        .expect("fetch pages")
+        .workers(4);
+    assert_eq!(pages.len(), 3);
+    let pages = pages
+        .map(|page| page.and_then(PageContent::into_image))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("download pages");
+
+    assert_eq!(pages.len(), 3);
+    for (number, page) in (1..).zip(pages) {
+        assert_eq!(page, common::page(1001, number), "page {number}");
+    }
+}
+
+#[test]
+fn expired_urls() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let pages = media(&serie, 1)
+        .fetch_pages(client)
+        .expect("fetch pages")
+        .workers(2);
+
+    // URLs expire while the pages are being downloaded.
+    server.expire_urls();
+    let pages = pages
+        .map(|page| page.and_then(PageContent::into_image))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("download pages");
+
+    assert_eq!(pages.len(), 3);
+    for (number, page) in (1..).zip(pages) {
+        assert_eq!(page, common::page(1001, number), "page {number}");
+    }
+}
+
+#[test]
+fn jp_expired_urls() {
+    let server = MockServer::start();
+    let client = server.region_client(Region::Jp);
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let mut pages = media(&serie, 1).fetch_pages(client).expect("fetch pages");
+
+    let first = pages.next().expect("page").expect("download page");
+    server.expire_urls();
+    let rest = pages
+        .map(|page| page.and_then(PageContent::into_image))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("download pages");
+
+    assert_eq!(first.into_image().expect("image"), common::page(1001, 1));
+    assert_eq!(rest, [common::page(1001, 2), common::page(1001, 3)]);
+}
+
+#[test]
+fn unscrambled_pages() {
+    let server = MockServer::start();
+    let client = server.client();
+    client.login(EMAIL, PASSWORD).expect("login");
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    let pages = fetch_pages(&client, media(&serie, 3));
+
+    assert_eq!(pages.len(), 2);
+    for (number, page) in (1..).zip(pages) {
+        assert_eq!(page, common::page(1003, number), "page {number}");
+    }
+}
+
+#[test]
+fn passthrough_pages() {
+    let server = MockServer::start();
+    let client = server.client();
+    client.login(EMAIL, PASSWORD).expect("login");
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let (unscrambled, scrambled) = (media(&serie, 3), media(&serie, 1));
+
+    let pages = unscrambled
+        .fetch_pages(client.clone())
+        .expect("fetch pages")
+        .passthrough(true)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("download pages");
+    let mut scrambled_pages = scrambled
+        .fetch_pages(client)
+        .expect("fetch pages")
+        .passthrough(true);
+
+    assert_eq!(pages.len(), 2);
+    for (number, page) in (1..).zip(pages) {
+        match page {
+            PageContent::Original { bytes, format } => {
+                assert_eq!(format, image::ImageFormat::Png);
+                assert_eq!(
+                    bytes,
+                    common::png(&common::page(1003, number)),
+                    "page {number}"
+                );
+            },
+            PageContent::Image(_) => panic!("page {number} was decoded"),
+        }
+    }
+    // Scrambled pages must still be fixed.
+    assert!(matches!(
+        scrambled_pages.next(),
+        Some(Ok(PageContent::Image(_)))
+    ));
+}
+
+#[test]
+fn jp_serie() {
+    let server = MockServer::start();
+    let client = server.region_client(Region::Jp);
+
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    assert_eq!(serie.title(), JP_SERIE_TITLE);
+    assert_eq!(serie.media_count(), 3);
+    assert_eq!(media(&serie, 2).title(), "002 - 第2話");
+    assert_eq!(media(&serie, 2).page_count(), None);
+    assert!(media(&serie, 1).is_available());
+    assert!(!media(&serie, 2).is_available());
+    assert!(!media(&serie, 3).is_available());
+}
+
+#[test]
+fn jp_serie_details() {
+    let server = MockServer::start();
+    let client = server.region_client(Region::Jp);
+
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    assert_eq!(serie.authors(), ["山田太郎"]);
+    assert_eq!(serie.description(), Some("モックの物語。"));
+    assert_eq!(serie.genres(), ["ファンタジー"]);
+    assert_eq!(serie.status(), Some(Status::Completed));
+    assert_eq!(serie.kind(), Some(Kind::Manga));
+    assert_eq!(serie.cover_url().map(Url::path), Some("/covers/42.jpg"),);
+}
+
+#[test]
+fn jp_scrambled_pages() {
+    let server = MockServer::start();
+    let client = server.region_client(Region::Jp);
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+
+    let pages = fetch_pages(&client, media(&serie, 1));
+
+    assert_eq!(pages.len(), 3);
+    for (number, page) in (1..).zip(pages) {
+        assert_eq!(page, common::page(1001, number), "page {number}");
+    }
+}
+
+#[test]
+fn cbz_archive() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    // The serie is a smartoon, read from left to right.
+    let direction = Config::default().options(None).direction(serie.kind());
+
+    let metadata = Metadata::new(&client, &serie, media, direction);
+
+    let destination = common::temp_path("cbz");
+    std::fs::create_dir_all(&destination).expect("create destination");
+    let path = destination.join(media.filename(OutputFormat::Cbz));
+
+    let pages = encode_pages(client, media, PageEncoder::default());
+    save_cbz(&path, &metadata, pages).expect("save CBZ");
+
+    assert!(!piconbiere::fs::part_path(&path).exists());
+    let bytes = std::fs::read(&path).expect("read CBZ");
+    std::fs::remove_dir_all(&destination).expect("cleanup");
+    let mut cbz = ZipArchive::new(Cursor::new(bytes)).expect("valid zip");
+    let names = cbz.file_names().map(ToOwned::to_owned).collect::<Vec<_>>();
+    assert_eq!(cbz.len(), 5);
+    assert!(names.contains(&"001 - Le commencement/".to_owned()));
+
+    let mut comic_info = String::new();
+    cbz.by_name("ComicInfo.xml")
+        .expect("ComicInfo.xml")
+        .read_to_string(&mut comic_info)
+        .expect("read ComicInfo.xml");
+    assert!(comic_info.contains("<Title>001 - Le commencement</Title>"));
+    assert!(comic_info.contains(&format!("<Series>{SERIE_TITLE}</Series>")));
+    assert!(comic_info.contains("<Number>1</Number>"));
+    assert!(comic_info.contains("<PageCount>3</PageCount>"));
+    assert!(
+        comic_info.contains(&format!("/fr/product/episode/{SERIE_ID}</Web>"))
+    );
+    assert!(comic_info.contains("<Manga>No</Manga>"));
+    assert!(comic_info.contains("<Writer>Jane Doe, John Smith</Writer>"));
+    assert!(comic_info.contains("<Genre>Action, Fantasy</Genre>"));
+    assert!(comic_info.contains(
+        "<Summary>Une histoire &lt;de&gt; test &amp; de mock.</Summary>"
+    ));
+    // Elements must follow the order of the ComicInfo schema.
+    let positions = [
+        "<Title>",
+        "<Series>",
+        "<Number>",
+        "<Summary>",
+        "<Writer>",
+        "<Genre>",
+        "<Web>",
+        "<PageCount>",
+        "<LanguageISO>",
+        "<Manga>",
+    ]
+    .map(|element| comic_info.find(element).expect(element));
+    assert!(
+        positions.windows(2).all(|pair| pair[0] < pair[1]),
+        "elements out of order: {comic_info}"
+    );
+    for (number, i) in (1..).zip(0..3) {
+        let mut file = cbz
+            .by_name(&format!("001 - Le commencement/{i:03}.webp"))
+            .expect("page in archive");
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).expect("read page");
+
+        let page = webp::Decoder::new(&data).decode().expect("decode WebP");
+        assert_eq!(page.to_image(), common::page(1001, number));
+    }
+}
+
+#[test]
+fn cbz_jpeg_pages() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    let metadata = Metadata::new(&client, &serie, media, Direction::Rtl);
+
+    let destination = common::temp_path("cbz_jpeg");
+    std::fs::create_dir_all(&destination).expect("create destination");
+    let path = destination.join(media.filename(OutputFormat::Cbz));
+
+    let pages =
+        encode_pages(client, media, PageEncoder::new(Encoding::Jpeg, 80));
+    save_cbz(&path, &metadata, pages).expect("save CBZ");
+
+    let bytes = std::fs::read(&path).expect("read CBZ");
+    std::fs::remove_dir_all(&destination).expect("cleanup");
+    let mut cbz = ZipArchive::new(Cursor::new(bytes)).expect("valid zip");
+    for i in 0..3 {
+        let mut file = cbz
+            .by_name(&format!("001 - Le commencement/{i:03}.jpg"))
+            .expect("page in archive");
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).expect("read page");
+
+        let page = image::load_from_memory_with_format(
+            &data,
+            image::ImageFormat::Jpeg,
+        )
+        .expect("decode JPEG");
+        assert_eq!((page.width(), page.height()), (120, 170));
+    }
+}
+
+#[test]
+fn cbz_failure() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    let metadata = Metadata::new(&client, &serie, media, Direction::Rtl);
+    let destination = common::temp_path("cbz_failure");
+    std::fs::create_dir_all(&destination).expect("create destination");
+    let path = destination.join(media.filename(OutputFormat::Cbz));
+
+    let pages = encode_pages(client, media, PageEncoder::default())
+        .chain(std::iter::once(Err(eyre::eyre!("broken page"))));
+    let err = save_cbz(&path, &metadata, pages).expect_err("broken page");
+
+    // Neither the archive, nor its tempfile, are left behind.
+    assert!(format!("{err:#}").contains("broken page"), "{err:#}");
+    assert!(!path.exists());
+    assert!(!piconbiere::fs::part_path(&path).exists());
+    std::fs::remove_dir_all(&destination).expect("cleanup");
+}
+
+#[test]
+fn epub_default_encoding() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    let metadata = Metadata::new(&client, &serie, media, Direction::Rtl);
+    let options = toml::from_str::<Config>(r#"output-format = "epub""#)
+        .expect("valid config")
+        .options(None);
+
+    let pages = encode_pages(client, media, options.page_encoder());
+    let bytes = epub::build(&metadata, pages).expect("build EPUB");
+
+    // WebP isn't a core media type of EPUB, readers may not display it.
+    let mut epub = ZipArchive::new(Cursor::new(bytes)).expect("valid zip");
+    let mut package = String::new();
+    epub.by_name("OEBPS/content.opf")
+        .expect("package document")
+        .read_to_string(&mut package)
+        .expect("read package document");
+    assert!(package.contains(r#"media-type="image/png""#));
+    assert!(!package.contains("image/webp"));
+}
+
+#[test]
+fn epub_book() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    let metadata = Metadata::new(&client, &serie, media, Direction::Rtl);
+
+    let pages =
+        encode_pages(client, media, PageEncoder::new(Encoding::Png, 90));
+    let bytes = epub::build(&metadata, pages).expect("build EPUB");
+
+    let mut epub = ZipArchive::new(Cursor::new(bytes)).expect("valid zip");
+    // 3 images and 3 pages, plus mimetype, container, nav and package.
+    assert_eq!(epub.len(), 10);
+    let mimetype = epub.by_index(0).expect("mimetype");
+    assert_eq!(mimetype.name(), "mimetype");
+    assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+    drop(mimetype);
+
+    let mut package = String::new();
+    epub.by_name("OEBPS/content.opf")
+        .expect("package document")
+        .read_to_string(&mut package)
+        .expect("read package document");
+    assert!(package.contains("<dc:title>001 - Le commencement</dc:title>"));
+    assert!(package.contains(SERIE_TITLE));
+    assert!(package.contains(r#"page-progression-direction="rtl""#));
+    assert!(package.contains(r#"href="images/001.png" media-type="image/png" properties="cover-image""#));
+
+    for number in 1..=3 {
+        let mut file = epub
+            .by_name(&format!("OEBPS/images/{number:03}.png"))
+            .expect("page in book");
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).expect("read page");
+
+        let page = image::load_from_memory(&data).expect("decode PNG");
+        assert_eq!(page, common::page(1001, number));
+    }
+}
+
+#[test]
+fn pdf_document() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    let metadata = Metadata::new(&client, &serie, media, Direction::Rtl);
+
+    let pages = media.fetch_pages(client).expect("fetch pages");
+    let bytes = pdf::build(&metadata, pipeline::encode(pages, pdf::encode))
+        .expect("build PDF");
+    let content = String::from_utf8_lossy(&bytes);
+
+    assert!(content.starts_with("%PDF-1.7\n"));
+    assert!(content.ends_with("%%EOF\n"));
+    assert_eq!(content.matches("/Type /Page ").count(), 3);
+    assert_eq!(content.matches("/Filter /DCTDecode").count(), 3);
+    assert!(content.contains("/MediaBox [0 0 120 170]"));
+    assert!(content.contains("/Direction /R2L"));
+    // Every object listed in the cross-reference table is where it says.
+    let xref = content.rfind("xref\n").expect("cross-reference table");
+    for (id, entry) in content[xref..].lines().skip(3).take(14).enumerate() {
+        let offset = entry[..10].parse::<usize>().expect("object offset");
+        assert!(
+            bytes[offset..].starts_with(format!("{} 0 obj", id + 1).as_bytes())
+        );
+    }
+}
+
+#[test]
+fn directory_output() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    let destination = common::temp_path("directory");
+    std::fs::create_dir_all(&destination).expect("create destination");
+    let path = destination.join(media.filename(OutputFormat::Directory));
+    let part_path = piconbiere::fs::part_path(&path);
+    assert!(!media.is_present_at(&destination, OutputFormat::Directory));
+
+    // Failures leave nothing behind.
+    let pages = encode_pages(client.clone(), media, PageEncoder::default())
+        .chain(std::iter::once(Err(eyre::eyre!("broken page"))));
+    let err = directory::write(&path, pages).expect_err("broken page");
+    assert!(format!("{err:#}").contains("broken page"), "{err:#}");
+    assert!(!path.exists());
+    assert!(!part_path.exists());
+
+    // Leftovers of an interrupted run, encoded differently, are discarded.
+    std::fs::create_dir_all(&part_path).expect("create leftovers");
+    std::fs::write(part_path.join("000.jpg"), "foo").expect("write leftover");
+    let pages = encode_pages(client, media, PageEncoder::default());
+    directory::write(&path, pages).expect("write pages");
+
+    assert!(media.is_present_at(&destination, OutputFormat::Directory));
+    assert!(!media.is_present_at(&destination, OutputFormat::Cbz));
+    assert!(!part_path.exists());
+    assert_eq!(std::fs::read_dir(&path).expect("list pages").count(), 3);
+    for (number, i) in (1..).zip(0..3) {
+        let data = std::fs::read(path.join(format!("{i:03}.webp")))
+            .expect("read page");
+
+        let page = webp::Decoder::new(&data).decode().expect("decode WebP");
+        assert_eq!(page.to_image(), common::page(1001, number));
+    }
+    std::fs::remove_dir_all(&destination).expect("cleanup");
+}
+
+#[test]
+fn staged_pages() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let media = media(&serie, 1);
+    let destination = common::temp_path("staging");
+    let staging = media.staging_path(&destination);
+    let fetch = || {
+        media
+            .fetch_pages(client.clone())
+            .expect("fetch pages")
+            .staging(staging.clone())
+            .map(|page| page.and_then(PageContent::into_image))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("download pages")
+    };
+
+    // Fixed pages are staged as they're processed.
+    let pages = fetch();
+    assert_eq!(pages.len(), 3);
+    for number in 1..=3 {
+        let path = staging.join(format!("{number:03}.png"));
+        let page = image::open(&path).expect("staged page");
+        assert_eq!(page, common::page(1001, number), "page {number}");
+    }
+
+    // Staged pages are reused as-is, only missing ones are downloaded.
+    let placeholder = common::page(1002, 1);
+    placeholder
+        .save(staging.join("002.png"))
+        .expect("replace staged page");
+    std::fs::remove_file(staging.join("003.png")).expect("remove page");
+    let pages = fetch();
+
+    assert_eq!(pages[0], common::page(1001, 1));
+    assert_eq!(pages[1], placeholder);
+    assert_eq!(pages[2], common::page(1001, 3));
+    assert!(staging.join("003.png").is_file());
+    std::fs::remove_dir_all(&destination).expect("cleanup");
+}
+
+#[test]
+fn serie_sidecar() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let destination = common::temp_path("sidecar");
+    std::fs::create_dir_all(&destination).expect("create destination");
+    let series_path = destination.join(sidecar::SERIES_FILENAME);
+    std::fs::write(&series_path, "{}").expect("write stale metadata");
+
+    sidecar::update(&client, &destination, &serie).expect("update sidecar");
+
+    let cover = std::fs::read(destination.join(sidecar::COVER_FILENAME))
+        .expect("read cover");
+    assert!(cover.starts_with(&[0xFF, 0xD8, 0xFF]));
+    let series = serde_json::from_slice::<serde_json::Value>(
+        &std::fs::read(&series_path).expect("read metadata"),
+    )
+    .expect("valid metadata");
+    assert_eq!(series["version"], "1.0.2");
+    assert_eq!(series["metadata"]["name"], SERIE_TITLE);
+    assert_eq!(series["metadata"]["comicid"], SERIE_ID);
+    assert_eq!(series["metadata"]["status"], "Continuing");
+    assert_eq!(series["metadata"]["total_issues"], 3);
+    assert_eq!(
+        series["metadata"]["description_text"],
+        "Une histoire <de> test & de mock."
+    );
+    std::fs::remove_dir_all(&destination).expect("cleanup");
+}
+
+#[test]
+fn failed_cover() {
+    let server = MockServer::start();
+    let client = server.client();
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    let destination = common::temp_path("failed_cover");
+    let series_path = destination.join(sidecar::SERIES_FILENAME);
+    let cover_path = destination.join(sidecar::COVER_FILENAME);
+    // The cover tempfile can't be created over a directory.
+    let blocker = piconbiere::fs::part_path(&cover_path);
+    std::fs::create_dir_all(&blocker).expect("block cover");
+
+    let err = sidecar::update(&client, &destination, &serie)
+        .expect_err("cover saving failure");
+
+    assert!(format!("{err:#}").contains("cover"), "{err:#}");
+
+    // Metadata are left as is, to try again on the next update.
+    assert!(!series_path.exists());
+    std::fs::remove_dir(&blocker).expect("unblock cover");
+    sidecar::update(&client, &destination, &serie).expect("update sidecar");
+    assert!(cover_path.is_file());
+    assert!(series_path.is_file());
+    std::fs::remove_dir_all(&destination).expect("cleanup");
+}
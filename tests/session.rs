@@ -0,0 +1,180 @@
+mod common;
+
+use common::{
+    MockServer, ACCESS_TOKEN, EMAIL, LOCKED_EMAIL, NO_SESSION_EMAIL, PASSWORD,
+    SERIE_ID,
+};
+use piconbiere::{LoginError, MediaType, Serie};
+use std::fs;
+
+/// Logs in and returns the reason of the failure.
+fn login_error(server: &MockServer, email: &str, password: &str) -> LoginError {
+    let client = server.client();
+
+    let err = client.login(email, password).expect_err("login failure");
+
+    assert!(!client.is_logged_in());
+    err.downcast_ref::<LoginError>()
+        .expect("login error")
+        .clone()
+}
+
+#[test]
+fn wrong_password() {
+    let server = MockServer::start();
+
+    let err = login_error(&server, EMAIL, "hunter3");
+
+    assert_eq!(err, LoginError::BadCredentials);
+}
+
+#[test]
+fn locked_account() {
+    let server = MockServer::start();
+
+    let err = login_error(&server, LOCKED_EMAIL, PASSWORD);
+
+    assert_eq!(err, LoginError::AccountLocked);
+}
+
+#[test]
+fn no_session() {
+    let server = MockServer::start();
+
+    let err = login_error(&server, NO_SESSION_EMAIL, PASSWORD);
+
+    assert_eq!(err, LoginError::NoSession);
+}
+
+#[test]
+fn restore_session() {
+    let server = MockServer::start();
+    let path = common::temp_path("restore_session.json");
+
+    let client = server.client();
+    client.login(EMAIL, PASSWORD).expect("login");
+    client.save_session(&path).expect("save session");
+    let client = server.client().with_session(&path).expect("load session");
+    fs::remove_file(&path).expect("remove session");
+
+    assert!(client.check_session().expect("check session"));
+    // Logged-in only API is reachable.
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    assert_eq!(
+        serie.media().filter(|media| media.is_available()).count(),
+        2
+    );
+}
+
+#[test]
+fn revoked_session() {
+    let server = MockServer::start();
+    let path = common::temp_path("revoked_session.json");
+
+    server
+        .client()
+        .with_access_token("revoked")
+        .expect("import access token")
+        .save_session(&path)
+        .expect("save session");
+    let client = server.client().with_session(&path).expect("load session");
+    fs::remove_file(&path).expect("remove session");
+
+    // Not expired yet, but unknown to the website.
+    assert!(client.is_logged_in());
+    assert!(!client.check_session().expect("check session"));
+}
+
+#[test]
+fn missing_session() {
+    let server = MockServer::start();
+    let path = common::temp_path("missing_session.json");
+
+    let client = server.client().with_session(&path).expect("load session");
+
+    assert!(!client.is_logged_in());
+}
+
+#[test]
+fn expired_session() {
+    let server = MockServer::start();
+    let path = common::temp_path("expired_session.json");
+    let domain = server.client().base_url().host_str().map(ToOwned::to_owned);
+    fs::write(
+        &path,
+        format!(
+            r#"{{"raw_cookie":"access_token=expired; Path=/; Expires=Thu, 01 Jan 2015 00:00:00 GMT","path":["/",true],"domain":{{"HostOnly":"{}"}},"expires":{{"AtUtc":"2015-01-01T00:00:00Z"}}}}"#,
+            domain.expect("host")
+        ),
+    )
+    .expect("write session");
+
+    let client = server.client().with_session(&path).expect("load session");
+    fs::remove_file(&path).expect("remove session");
+
+    assert!(!client.is_logged_in());
+}
+
+#[test]
+fn import_cookies_file() {
+    let server = MockServer::start();
+    let path = common::temp_path("cookies.txt");
+    let domain = server.client().base_url().host_str().map(ToOwned::to_owned);
+    fs::write(
+        &path,
+        format!(
+            "# Netscape HTTP Cookie File\n\
+             #HttpOnly_{}\tFALSE\t/\tFALSE\t0\taccess_token\t{ACCESS_TOKEN}\n\
+             .example.com\tTRUE\t/\tFALSE\t0\tunrelated\tfoo\n",
+            domain.expect("host")
+        ),
+    )
+    .expect("write cookies");
+
+    let client = server
+        .client()
+        .with_cookies_file(&path)
+        .expect("import cookies");
+    fs::remove_file(&path).expect("remove cookies");
+
+    assert!(client.check_session().expect("check session"));
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    assert_eq!(
+        serie.media().filter(|media| media.is_available()).count(),
+        2
+    );
+}
+
+#[test]
+fn import_access_token() {
+    let server = MockServer::start();
+
+    let client = server
+        .client()
+        .with_access_token(ACCESS_TOKEN)
+        .expect("import access token");
+
+    assert!(client.check_session().expect("check session"));
+    let serie = Serie::new(&client, SERIE_ID.into(), MediaType::Episode)
+        .expect("serie");
+    assert_eq!(
+        serie.media().filter(|media| media.is_available()).count(),
+        2
+    );
+}
+
+#[test]
+fn bogus_access_token() {
+    let server = MockServer::start();
+
+    let client = server
+        .client()
+        .with_access_token("bogus")
+        .expect("import access token");
+
+    // There is a session cookie, but the website doesn't know it.
+    assert!(client.is_logged_in());
+    assert!(!client.check_session().expect("check session"));
+}